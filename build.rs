@@ -0,0 +1,31 @@
+use std::process::Command;
+
+fn main() {
+    // Missing paths would rerun this every build, e.g. when built from a published crate.
+    if std::path::Path::new(".git").exists() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        println!("cargo:rerun-if-changed=.git/refs");
+    } else {
+        println!("cargo:rerun-if-changed=build.rs");
+    }
+
+    let commit =
+        git(&["rev-parse", "--short=12", "HEAD"]).unwrap_or_else(|| String::from("unknown"));
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+        .is_some_and(|status| !status.is_empty());
+    let built = humantime::format_rfc3339_seconds(std::time::SystemTime::now());
+
+    println!(
+        "cargo:rustc-env=GENTLE_COMMIT={commit}{}",
+        if dirty { "-dirty" } else { "" }
+    );
+    println!("cargo:rustc-env=GENTLE_BUILT={built}");
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let out = Command::new("git").args(args).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
@@ -1,203 +1,1674 @@
 use anyhow::Context;
-use std::{collections::*, path::*};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::{
+    collections::*,
+    io::Read,
+    path::*,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
 use vfs::*;
 
-const DEDUPLICATE_LARGER_THAN: u64 = 1024;
+pub const DEFAULT_DEDUP_THRESHOLD: u64 = 1024;
+/// Starts a placeholder, followed by a newline, the hash algorithm, a space and the hash of the
+/// file in hex. Placeholders saved before the algorithm was recorded follow this with the blake3
+/// hash directly.
 const HASHED_FILE_PREFIX: &[u8] = b"GENTLE HASHED";
+/// Longer files are never placeholders.
+const MAX_PLACEHOLDER_LEN: u64 = 256;
+const COMPRESSED_BLOB_HEADER: &[u8] = b"GENTLE ZSTD\n";
+/// Starts a blob listing, one per line, the hashes of the chunk blobs that make up the file.
+const CHUNKED_BLOB_HEADER: &[u8] = b"GENTLE CHUNKS\n";
+/// FastCDC's minimum, average and maximum chunk sizes.
+const CHUNK_SIZES: (u32, u32, u32) = (256 * 1024, 1024 * 1024, 4 * 1024 * 1024);
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+/// Directories that are elsewhere on other machines, by the variable naming them and where they
+/// are under the home directory if it's unset. Files under one are saved relative to it, and
+/// loaded under wherever it is on the loading machine.
+const ROOTS: &[(&str, &str)] = &[
+    ("XDG_CACHE_HOME", ".cache"),
+    ("CARGO_HOME", ".cargo"),
+    ("GOPATH", "go"),
+];
+
+/// How large files are named in the cache. Each placeholder records the algorithm it was saved
+/// with, so loading works whichever saved it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    fn hasher(self) -> Hasher {
+        match self {
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::default()),
+        }
+    }
+
+    fn hash(self, bytes: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(bytes);
+        hasher.finalize()
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashAlgorithm::Blake3 => write!(f, "blake3"),
+            HashAlgorithm::Sha256 => write!(f, "sha256"),
+        }
+    }
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            _ => Err(anyhow::anyhow!("unknown hash algorithm: {s:?}")),
+        }
+    }
+}
+
+enum Hasher {
+    // Boxed, it's much the larger.
+    Blake3(Box<blake3::Hasher>),
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        use sha2::Digest;
+        match self {
+            Hasher::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+        }
+    }
+
+    /// The hash in lowercase hex.
+    fn finalize(self) -> String {
+        use sha2::Digest;
+        match self {
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Sha256(hasher) => hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        }
+    }
+}
+
+impl std::io::Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
 
-pub fn load(from: PathBuf) -> anyhow::Result<()> {
+/// The algorithm and hash a placeholder refers to its large file by, if it's one.
+fn parse_placeholder(contents: &[u8]) -> Option<(HashAlgorithm, &str)> {
+    let rest = std::str::from_utf8(contents.strip_prefix(HASHED_FILE_PREFIX)?).ok()?;
+    let (algorithm, hash) = match rest.strip_prefix('\n') {
+        Some(tagged) => {
+            let (algorithm, hash) = tagged.split_once(' ')?;
+            (algorithm.parse().ok()?, hash)
+        }
+        None => (HashAlgorithm::Blake3, rest),
+    };
+
+    let hex = !hash.is_empty() && hash.bytes().all(|b| b.is_ascii_hexdigit());
+    hex.then_some((algorithm, hash))
+}
+
+pub fn load(from: PathBuf, hardlinks: bool, verify: bool) -> anyhow::Result<()> {
     let fs = PhysicalFS::new("/");
     let cache = Cache::new(
         &fs,
+        "/",
         &path_to_string(from)?,
         &path_to_string(std::env::current_dir()?)?,
+    )
+    .with_roots(roots(|var| std::env::var(var).ok()))
+    .with_hardlinks(hardlinks)
+    .with_verify(verify);
+
+    cache.load()?;
+
+    Ok(())
+}
+
+pub fn gc(dir: PathBuf, max_size: Option<u64>) -> anyhow::Result<GcStats> {
+    let fs = PhysicalFS::new("/");
+    let cache = Cache::new(
+        &fs,
+        "/",
+        &path_to_string(dir)?,
+        &path_to_string(std::env::current_dir()?)?,
+    );
+
+    cache.gc(max_size)
+}
+
+pub fn stats(dir: PathBuf, top: usize) -> anyhow::Result<CacheStats> {
+    let fs = PhysicalFS::new("/");
+    let cache = Cache::new(
+        &fs,
+        "/",
+        &path_to_string(dir)?,
+        &path_to_string(std::env::current_dir()?)?,
     );
 
-    cache.load()?;
+    cache.stats(top)
+}
+
+/// The home directory and the other `ROOTS` as vfs paths, by the variable naming them.
+fn roots(var: impl Fn(&str) -> Option<String>) -> Vec<(String, String)> {
+    let var = |name: &str| var(name).filter(|value| !value.is_empty());
+    let Some(home) = var("HOME").or_else(|| var("USERPROFILE")) else {
+        return Vec::new();
+    };
+    let home = normalize(&home).trim_end_matches('/').to_string();
+
+    let mut roots = vec![(String::from("HOME"), home.clone())];
+    for &(name, default) in ROOTS {
+        let path = match var(name) {
+            Some(path) => normalize(&path).trim_end_matches('/').to_string(),
+            None => format!("{home}/{default}"),
+        };
+        roots.push((name.to_string(), path));
+    }
+    // Otherwise the root directory would hold every absolute path.
+    roots.retain(|(_, path)| !path.is_empty());
+    roots
+}
+
+fn path_to_string(path: PathBuf) -> anyhow::Result<String> {
+    path.to_str()
+        .ok_or(anyhow::anyhow!("path not unicode: {path:?}"))
+        .map(|s| s.to_string())
+}
+
+pub fn save(
+    to: PathBuf,
+    compression_level: i32,
+    dedup_threshold: u64,
+    chunk_threshold: Option<u64>,
+    hash_algorithm: HashAlgorithm,
+    discovery: &gentle::targets::Discovery,
+) -> anyhow::Result<()> {
+    let fs = PhysicalFS::new("/");
+    let cache = Cache::new(
+        &fs,
+        "/",
+        &path_to_string(to)?,
+        &path_to_string(std::env::current_dir()?)?,
+    )
+    .with_roots(roots(|var| std::env::var(var).ok()))
+    .with_compression_level(compression_level)
+    .with_dedup_threshold(dedup_threshold)
+    .with_chunk_threshold(chunk_threshold)
+    .with_hash_algorithm(hash_algorithm);
+
+    let targets = gentle::targets::targets(discovery)?;
+    let cache_paths = targets
+        .iter()
+        .flat_map(|t| t.cache_paths())
+        .filter_map(|path| match path_to_string(path) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                eprintln!("Not caching: {e}");
+                None
+            }
+        })
+        .collect::<HashSet<String>>();
+
+    let mut excludes = GlobSetBuilder::new();
+    for glob in targets.iter().flat_map(|t| t.cache_excludes()) {
+        excludes.add(Glob::new(&glob)?);
+    }
+
+    cache
+        .with_excludes(excludes.build()?)
+        .save(&cache_paths.into_iter().collect::<Vec<_>>())
+}
+
+struct FileCopy {
+    from: String,
+    to: String,
+    len: u64,
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Save,
+    Load,
+}
+
+struct Cache<'f, F: FileSystem> {
+    fs: &'f F,
+    /// Where `fs` is rooted on disk, for the operations `vfs` doesn't support.
+    root: PathBuf,
+    cache: String,
+    pwd: String,
+    compression_level: i32,
+    hardlinks: bool,
+    /// Files at least this large are stored once in `large_files`, behind a placeholder.
+    dedup_threshold: u64,
+    /// Large files at least this large are split into content-defined chunks, each stored once,
+    /// so files that only change slightly between saves share most of their storage.
+    chunk_threshold: Option<u64>,
+    /// Check each large file against its hash before loading it.
+    verify: bool,
+    /// Files and directories not to save.
+    excludes: GlobSet,
+    /// Directories saved under `roots/{name}` rather than where they are, by name.
+    roots: Vec<(String, String)>,
+    /// What large files saved now are named by.
+    hash_algorithm: HashAlgorithm,
+}
+
+impl<'f, F: FileSystem> Cache<'f, F> {
+    fn new(
+        fs: &'f F,
+        root: impl Into<PathBuf>,
+        cache: impl AsRef<str>,
+        pwd: impl AsRef<str>,
+    ) -> Self {
+        Self {
+            fs,
+            root: root.into(),
+            cache: normalize(cache.as_ref()),
+            pwd: normalize(pwd.as_ref()),
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            hardlinks: true,
+            dedup_threshold: DEFAULT_DEDUP_THRESHOLD,
+            chunk_threshold: None,
+            verify: false,
+            excludes: GlobSet::empty(),
+            roots: Vec::new(),
+            hash_algorithm: HashAlgorithm::default(),
+        }
+    }
+
+    fn with_excludes(self, excludes: GlobSet) -> Self {
+        Self { excludes, ..self }
+    }
+
+    fn with_hash_algorithm(self, hash_algorithm: HashAlgorithm) -> Self {
+        Self {
+            hash_algorithm,
+            ..self
+        }
+    }
+
+    fn with_roots(self, roots: Vec<(String, String)>) -> Self {
+        Self { roots, ..self }
+    }
+
+    fn with_verify(self, verify: bool) -> Self {
+        Self { verify, ..self }
+    }
+
+    fn with_dedup_threshold(self, dedup_threshold: u64) -> Self {
+        Self {
+            dedup_threshold,
+            ..self
+        }
+    }
+
+    fn with_chunk_threshold(self, chunk_threshold: Option<u64>) -> Self {
+        Self {
+            chunk_threshold,
+            ..self
+        }
+    }
+
+    fn with_hardlinks(self, hardlinks: bool) -> Self {
+        Self { hardlinks, ..self }
+    }
+
+    fn with_compression_level(self, compression_level: i32) -> Self {
+        Self {
+            compression_level,
+            ..self
+        }
+    }
+
+    pub(crate) fn save(&self, paths: &[impl AsRef<str>]) -> anyhow::Result<()> {
+        self.create_dir_all(&format!("{}/large_files", self.cache))?;
+        self.create_dir_all(&format!("{}/tmp", self.cache))?;
+
+        let mut files = Vec::new();
+        for path in paths {
+            let path = normalize(path.as_ref());
+            if let Some((name, rest)) = self.root_of(&path) {
+                self.walk(
+                    &path,
+                    &format!("{}/roots/{name}{rest}", self.cache),
+                    &mut files,
+                )?;
+            } else if let Some((drive, rest)) = split_drive(&path) {
+                self.walk(
+                    &path,
+                    &format!("{}/absolute/{drive}{rest}", self.cache),
+                    &mut files,
+                )?;
+            } else if path.starts_with('/') {
+                self.walk(&path, &format!("{}/absolute{path}", self.cache), &mut files)?;
+            } else {
+                self.walk(
+                    &format!("{}/{path}", self.pwd),
+                    &format!("{}/relative/{path}", self.cache),
+                    &mut files,
+                )?;
+            }
+        }
+
+        self.copy_files(&files, Direction::Save)
+    }
+
+    /// The name of the most specific root `path` is under, and the rest of `path` after it.
+    fn root_of<'p>(&self, path: &'p str) -> Option<(&str, &'p str)> {
+        self.roots
+            .iter()
+            .filter_map(|(name, root)| {
+                let rest = path.strip_prefix(root.as_str())?;
+                (rest.is_empty() || rest.starts_with('/')).then_some((name, root.len(), rest))
+            })
+            .max_by_key(|&(_, len, _)| len)
+            .map(|(name, _, rest)| (name.as_str(), rest))
+    }
+
+    /// Recreates the directories and links under `from` at `to`, collecting the files to copy.
+    fn walk(&self, from: &str, to: &str, files: &mut Vec<FileCopy>) -> anyhow::Result<()> {
+        if self.excludes.is_match(from) {
+            return Ok(());
+        }
+
+        if let Some(target) = self.read_link(from)? {
+            return self.symlink(&target, to);
+        }
+
+        if !self.fs.exists(from).context("Checking file existence")? {
+            return Ok(());
+        }
+
+        let metadata = self.fs.metadata(from).context("Getting file metadata")?;
+        match metadata.file_type {
+            VfsFileType::Directory => {
+                // Created up front so empty directories survive the round trip.
+                self.create_dir_all(to)?;
+
+                for file in self.read_dir(from)? {
+                    self.walk(
+                        &format!("{from}/{file}"),
+                        &format!("{to}/{file}").replace("//", "/"),
+                        files,
+                    )?;
+                }
+            }
+
+            VfsFileType::File => files.push(FileCopy {
+                from: from.to_string(),
+                to: to.to_string(),
+                len: metadata.len,
+            }),
+        }
+
+        Ok(())
+    }
+
+    fn copy_files(&self, files: &[FileCopy], direction: Direction) -> anyhow::Result<()> {
+        let next = AtomicUsize::new(0);
+        let threads = num_cpus::get().min(files.len()).max(1);
+
+        std::thread::scope(|scope| {
+            let workers = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| -> anyhow::Result<()> {
+                        while let Some(file) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            match direction {
+                                Direction::Save => self.save_file(&file.from, &file.to, file.len),
+                                Direction::Load => self.load_file(&file.from, &file.to, file.len),
+                            }
+                            .with_context(|| format!("Copying {:?}", file.from))?;
+                        }
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().expect("cache worker panicked"))
+        })
+    }
+
+    fn save_file(&self, from: &str, to: &str, len: u64) -> anyhow::Result<()> {
+        // Small files that look like placeholders are stored as blobs too, so anything in the
+        // cache that looks like a placeholder is one.
+        if len < self.dedup_threshold && !self.looks_like_placeholder(from, len)? {
+            return self.write_atomic(to, |tmp| {
+                self.fs.copy_file(from, tmp)?;
+                self.copy_modified(from, tmp)
+            });
+        }
+
+        // The blob goes first so a placeholder never points at a blob that isn't there.
+        let hash = if self
+            .chunk_threshold
+            .is_some_and(|threshold| len >= threshold)
+        {
+            self.save_chunked(from)?
+        } else {
+            self.save_blob(from)?
+        };
+
+        self.write_atomic(to, |tmp| {
+            let mut write = self.fs.create_file(tmp)?;
+            write.write_all(HASHED_FILE_PREFIX)?;
+            write!(write, "\n{} {hash}", self.hash_algorithm)?;
+            drop(write);
+            // Blobs are shared so the source's mtime is kept on its placeholder.
+            self.copy_modified(from, tmp)
+        })
+    }
+
+    /// Stores `from` whole as the blob named by its hash.
+    fn save_blob(&self, from: &str) -> anyhow::Result<String> {
+        let mut hasher = self.hash_algorithm.hasher();
+        std::io::copy(
+            &mut self
+                .fs
+                .open_file(from)
+                .with_context(|| format!("Opening {from:?}"))?,
+            &mut hasher,
+        )?;
+        let hash = hasher.finalize();
+
+        let blob = format!("{}/large_files/{hash}", self.cache);
+        self.write_atomic(&blob, |tmp| {
+            // Uncompressed blobs can be hardlinked into place on load, which keeps their mtime.
+            if self.compression_level == 0 {
+                self.fs.copy_file(from, tmp)?;
+                return self.copy_modified(from, tmp);
+            }
+
+            let mut write = self.fs.create_file(tmp)?;
+            write.write_all(COMPRESSED_BLOB_HEADER)?;
+            let mut encoder = zstd::Encoder::new(write, self.compression_level)?;
+            std::io::copy(&mut self.fs.open_file(from)?, &mut encoder)?;
+            encoder.finish()?;
+            self.copy_permissions(from, tmp)
+        })?;
+
+        Ok(hash)
+    }
+
+    /// Stores each content-defined chunk of `from` as a blob, skipping those already stored, then
+    /// the list of them as the blob named by the hash of the whole file.
+    fn save_chunked(&self, from: &str) -> anyhow::Result<String> {
+        let (min, average, max) = CHUNK_SIZES;
+        let read = self
+            .fs
+            .open_file(from)
+            .with_context(|| format!("Opening {from:?}"))?;
+
+        let mut hasher = self.hash_algorithm.hasher();
+        let mut manifest = CHUNKED_BLOB_HEADER.to_vec();
+        for chunk in fastcdc::v2020::StreamCDC::new(read, min, average, max) {
+            let chunk = chunk.with_context(|| format!("Chunking {from:?}"))?;
+            hasher.update(&chunk.data);
+            let hash = self.hash_algorithm.hash(&chunk.data);
+            manifest.extend_from_slice(hash.as_bytes());
+            manifest.push(b'\n');
+
+            // Chunks are only ever renamed into place whole, so one that exists is complete.
+            let blob = format!("{}/large_files/{hash}", self.cache);
+            if self.fs.exists(&blob)? {
+                continue;
+            }
+            self.write_atomic(&blob, |tmp| {
+                let mut write = self.fs.create_file(tmp)?;
+                if self.compression_level == 0 {
+                    write.write_all(&chunk.data)?;
+                    return Ok(());
+                }
+
+                write.write_all(COMPRESSED_BLOB_HEADER)?;
+                zstd::stream::copy_encode(&chunk.data[..], write, self.compression_level)?;
+                Ok(())
+            })?;
+        }
+        let hash = hasher.finalize();
+
+        let blob = format!("{}/large_files/{hash}", self.cache);
+        self.write_atomic(&blob, |tmp| {
+            self.fs.create_file(tmp)?.write_all(&manifest)?;
+            self.copy_permissions(from, tmp)
+        })?;
+
+        Ok(hash)
+    }
+
+    /// Writes `path` by way of a temporary file renamed into place, so neither an interrupted save
+    /// nor concurrent writers of the same blob leave a partial file behind. Renaming also replaces
+    /// rather than writes through any hardlink an earlier load made to the working tree.
+    fn write_atomic(
+        &self,
+        path: &str,
+        write: impl FnOnce(&str) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        static NEXT_TEMP: AtomicUsize = AtomicUsize::new(0);
+        let tmp = format!(
+            "{}/tmp/{}-{}",
+            self.cache,
+            std::process::id(),
+            NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+        );
+
+        let written = write(&tmp).and_then(|()| {
+            std::fs::File::open(self.real_path(&tmp))?.sync_all()?;
+            Ok(())
+        });
+        if let Err(e) = written {
+            self.unlink(&tmp);
+            return Err(e);
+        }
+        std::fs::rename(self.real_path(&tmp), self.real_path(path))
+            .with_context(|| format!("Moving {tmp:?} to {path:?}"))?;
+
+        Ok(())
+    }
+
+    fn load_file(&self, from: &str, to: &str, len: u64) -> anyhow::Result<()> {
+        if !self.looks_like_placeholder(from, len)? {
+            return self.link_or_copy(from, to, from);
+        }
+
+        let (algorithm, hash) = self.read_placeholder(from)?;
+        let blob = format!("{}/large_files/{hash}", self.cache);
+
+        if self.verify {
+            let mut hasher = algorithm.hasher();
+            std::io::copy(&mut self.open_blob(&blob)?, &mut hasher)
+                .with_context(|| format!("Reading {blob:?}"))?;
+            let actual = hasher.finalize();
+            anyhow::ensure!(
+                actual == hash,
+                "Large file {hash} is corrupt, its contents hash to {actual}"
+            );
+        }
+
+        // Blobs written before compression was added have no header and are copied as is.
+        if !self.starts_with(&blob, COMPRESSED_BLOB_HEADER)?
+            && !self.starts_with(&blob, CHUNKED_BLOB_HEADER)?
+        {
+            return self.link_or_copy(&blob, to, from);
+        }
+
+        self.unlink(to);
+        std::io::copy(&mut self.open_blob(&blob)?, &mut self.fs.create_file(to)?)
+            .with_context(|| format!("Decompressing {blob:?}"))?;
+        self.copy_permissions(&blob, to)?;
+        self.copy_modified(from, to)?;
+
+        Ok(())
+    }
+
+    /// Opens a blob for reading its original contents, decompressing and reassembling chunks if
+    /// needed.
+    fn open_blob(&self, blob: &str) -> anyhow::Result<Box<dyn Read + '_>> {
+        if let Some(chunks) = self.chunks(blob)? {
+            return Ok(Box::new(ChunksReader {
+                cache: self,
+                chunks: chunks.into_iter(),
+                current: None,
+            }));
+        }
+
+        let mut read = self.fs.open_file(blob)?;
+        if !self.starts_with(blob, COMPRESSED_BLOB_HEADER)? {
+            return Ok(Box::new(read));
+        }
+
+        read.read_exact(&mut [0; COMPRESSED_BLOB_HEADER.len()])?;
+        Ok(Box::new(zstd::Decoder::new(read)?))
+    }
+
+    /// The hashes of the chunks making up `blob`, in order, if it was saved in chunks.
+    fn chunks(&self, blob: &str) -> anyhow::Result<Option<Vec<String>>> {
+        if !self.starts_with(blob, CHUNKED_BLOB_HEADER)? {
+            return Ok(None);
+        }
+
+        let mut manifest = String::new();
+        self.fs.open_file(blob)?.read_to_string(&mut manifest)?;
+        Ok(Some(manifest.lines().skip(1).map(str::to_string).collect()))
+    }
+
+    /// Places `from` at `to` with the mtime of `modified_from`, hardlinking if that doesn't need
+    /// a different mtime than `from` already has.
+    fn link_or_copy(&self, from: &str, to: &str, modified_from: &str) -> anyhow::Result<()> {
+        // Never write through a hardlink an earlier load made into the cache.
+        self.unlink(to);
+
+        if self.hardlinks && self.modified(from)? == self.modified(modified_from)? {
+            // Fails across filesystems, in which case we fall back to copying.
+            if std::fs::hard_link(self.real_path(from), self.real_path(to)).is_ok() {
+                return Ok(());
+            }
+        }
+
+        self.fs.copy_file(from, to)?;
+        self.copy_modified(modified_from, to)
+    }
+
+    /// The names of the entries in `dir`, skipping, with a warning, any that aren't unicode.
+    /// `vfs` panics on those.
+    fn read_dir(&self, dir: &str) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(self.real_path(dir))? {
+            match entry?.file_name().into_string() {
+                Ok(name) => names.push(name),
+                Err(name) => eprintln!("Not caching {dir}/{name:?}: path not unicode"),
+            }
+        }
+        Ok(names)
+    }
+
+    fn unlink(&self, path: &str) {
+        let _ = std::fs::remove_file(self.real_path(path));
+    }
+
+    fn starts_with(&self, path: &str, prefix: &[u8]) -> anyhow::Result<bool> {
+        let file = self
+            .fs
+            .open_file(path)
+            .with_context(|| format!("Opening {path:?}"))?;
+        let mut start = Vec::with_capacity(prefix.len());
+        file.take(prefix.len() as u64).read_to_end(&mut start)?;
+        Ok(start == prefix)
+    }
+
+    fn modified(&self, path: &str) -> anyhow::Result<SystemTime> {
+        Ok(std::fs::metadata(self.real_path(path))?.modified()?)
+    }
+
+    fn copy_modified(&self, from: &str, to: &str) -> anyhow::Result<()> {
+        std::fs::File::open(self.real_path(to))?.set_modified(self.modified(from)?)?;
+        Ok(())
+    }
+
+    fn copy_permissions(&self, from: &str, to: &str) -> anyhow::Result<()> {
+        let permissions = std::fs::metadata(self.real_path(from))?.permissions();
+        std::fs::set_permissions(self.real_path(to), permissions)?;
+        Ok(())
+    }
+
+    fn looks_like_placeholder(&self, path: &str, len: u64) -> anyhow::Result<bool> {
+        if len > MAX_PLACEHOLDER_LEN {
+            return Ok(false);
+        }
+
+        self.starts_with(path, HASHED_FILE_PREFIX)
+    }
+
+    fn read_placeholder(&self, path: &str) -> anyhow::Result<(HashAlgorithm, String)> {
+        let mut contents = Vec::new();
+        self.fs.open_file(path)?.read_to_end(&mut contents)?;
+        let (algorithm, hash) = parse_placeholder(&contents)
+            .ok_or_else(|| anyhow::anyhow!("{path:?} is not a valid placeholder"))?;
+        Ok((algorithm, hash.to_string()))
+    }
+
+    fn real_path(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    fn read_link(&self, path: &str) -> anyhow::Result<Option<PathBuf>> {
+        let real = self.real_path(path);
+        match std::fs::symlink_metadata(&real) {
+            Ok(m) if m.file_type().is_symlink() => Ok(Some(
+                std::fs::read_link(&real).with_context(|| format!("Reading link {path:?}"))?,
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    fn symlink(&self, target: &Path, path: &str) -> anyhow::Result<()> {
+        let real = self.real_path(path);
+        if let Ok(m) = std::fs::symlink_metadata(&real) {
+            if !m.is_dir() {
+                std::fs::remove_file(&real)?;
+            }
+        }
+
+        create_symlink(target, &real).with_context(|| format!("Creating link {path:?}"))?;
+        Ok(())
+    }
+
+    fn create_dir_all(&self, dir: &str) -> anyhow::Result<()> {
+        // The root, which always exists, and the base case for absolute paths.
+        let dir = dir.trim_end_matches('/');
+        if dir.is_empty() || self.fs.exists(dir)? {
+            return Ok(());
+        }
+
+        if let Some((parent, _)) = dir.rsplit_once('/') {
+            self.create_dir_all(parent)?;
+        }
+        self.fs.create_dir(dir)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn load(&self) -> anyhow::Result<()> {
+        let mut files = Vec::new();
+        let absolute = format!("{}/absolute", self.cache);
+        if cfg!(windows) {
+            // Each drive is saved under its letter, see `split_drive`.
+            if self.fs.exists(&absolute)? {
+                for drive in self.fs.read_dir(&absolute)? {
+                    self.walk(
+                        &format!("{absolute}/{drive}"),
+                        &format!("{drive}:"),
+                        &mut files,
+                    )
+                    .context("Loading absolute paths")?;
+                }
+            }
+        } else {
+            self.walk(&absolute, "/", &mut files)
+                .context("Loading absolute paths")?;
+        }
+        self.walk(&format!("{}/relative", self.cache), &self.pwd, &mut files)
+            .context("Loading relative paths")?;
+        for (name, root) in &self.roots {
+            self.walk(&format!("{}/roots/{name}", self.cache), root, &mut files)
+                .with_context(|| format!("Loading paths under {name}"))?;
+        }
+
+        self.copy_files(&files, Direction::Load)
+    }
+
+    /// Deletes large files no placeholder refers to, then, if `max_size` is set, evicts the
+    /// least recently saved ones (with their placeholders) until they total at most `max_size`
+    /// bytes. Chunks go once no kept large file is made of them.
+    pub(crate) fn gc(&self, max_size: Option<u64>) -> anyhow::Result<GcStats> {
+        let references = self.references()?;
+
+        let large_files = format!("{}/large_files", self.cache);
+        let mut chunks = HashMap::new();
+        // How many times kept large files list each chunk.
+        let mut chunk_references = HashMap::<String, usize>::new();
+        for hash in references.keys() {
+            let blob = format!("{large_files}/{hash}");
+            if !self.fs.exists(&blob)? {
+                continue;
+            }
+            if let Some(listed) = self.chunks(&blob)? {
+                for chunk in &listed {
+                    *chunk_references.entry(chunk.clone()).or_default() += 1;
+                }
+                chunks.insert(hash.clone(), listed);
+            }
+        }
+
+        let mut stats = GcStats::default();
+        let mut blobs = Vec::new();
+        let mut total = 0;
+        if self.fs.exists(&large_files)? {
+            for hash in self.fs.read_dir(&large_files)? {
+                let blob = format!("{large_files}/{hash}");
+                let len = self.fs.metadata(&blob)?.len;
+
+                if references.contains_key(&hash) {
+                    // Blobs are rewritten, and so recreated, by every save that uses them, while
+                    // their mtime is that of the source file.
+                    let metadata = std::fs::metadata(self.real_path(&blob))?;
+                    let saved = metadata.created().or_else(|_| metadata.modified())?;
+                    blobs.push((saved, hash, len));
+                    total += len;
+                } else if chunk_references.contains_key(&hash) {
+                    total += len;
+                } else {
+                    self.fs.remove_file(&blob)?;
+                    stats.removed += 1;
+                    stats.freed += len;
+                }
+            }
+        }
+
+        if let Some(max_size) = max_size {
+            blobs.sort();
+
+            let mut evicted = HashSet::new();
+            for (_, hash, len) in blobs {
+                if total <= max_size {
+                    break;
+                }
+
+                for placeholder in &references[&hash] {
+                    self.fs.remove_file(placeholder)?;
+                }
+                evicted.insert(hash.clone());
+                // Still needed as a chunk of a file that's kept.
+                if chunk_references.get(&hash).is_some_and(|&n| n > 0) {
+                    continue;
+                }
+                self.fs.remove_file(&format!("{large_files}/{hash}"))?;
+                total -= len;
+                stats.removed += 1;
+                stats.freed += len;
+
+                for chunk in chunks.get(&hash).into_iter().flatten() {
+                    let listed = chunk_references.get_mut(chunk).expect("counted above");
+                    *listed -= 1;
+                    if *listed > 0 || (references.contains_key(chunk) && !evicted.contains(chunk)) {
+                        continue;
+                    }
+
+                    let blob = format!("{large_files}/{chunk}");
+                    let len = self.fs.metadata(&blob)?.len;
+                    self.fs.remove_file(&blob)?;
+                    total -= len;
+                    stats.removed += 1;
+                    stats.freed += len;
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+
+    pub(crate) fn stats(&self, top: usize) -> anyhow::Result<CacheStats> {
+        let references = self.references()?;
+
+        let large_files = format!("{}/large_files", self.cache);
+        let mut stats = CacheStats {
+            placeholders: references.values().map(Vec::len).sum(),
+            ..Default::default()
+        };
+
+        // How many copies of its contents each blob stands in for, counting chunks once for each
+        // copy of each large file made of them.
+        let mut uses = HashMap::<String, u64>::new();
+        for (hash, placeholders) in &references {
+            *uses.entry(hash.clone()).or_default() += placeholders.len() as u64;
+
+            let blob = format!("{large_files}/{hash}");
+            if !self.fs.exists(&blob)? {
+                continue;
+            }
+            for chunk in self.chunks(&blob)?.into_iter().flatten() {
+                *uses.entry(chunk).or_default() += placeholders.len() as u64;
+            }
+        }
+
+        let mut blobs = Vec::new();
+        if self.fs.exists(&large_files)? {
+            for hash in self.fs.read_dir(&large_files)? {
+                let len = self.fs.metadata(&format!("{large_files}/{hash}"))?.len;
+
+                stats.blobs += 1;
+                stats.blob_bytes += len;
+                if let Some(uses) = uses.get(&hash) {
+                    stats.dedup_savings += len * (uses - 1);
+                }
+                blobs.push((len, hash));
+            }
+        }
+
+        blobs.sort_by(|a, b| b.cmp(a));
+        stats.largest = blobs
+            .into_iter()
+            .take(top)
+            .map(|(len, hash)| (hash, len))
+            .collect();
+
+        Ok(stats)
+    }
+
+    /// Every placeholder in the cache, by the hash it refers to.
+    fn references(&self) -> anyhow::Result<HashMap<String, Vec<String>>> {
+        let mut references = HashMap::new();
+        for tree in ["absolute", "relative", "roots"] {
+            self.find_placeholders(&format!("{}/{tree}", self.cache), &mut references)?;
+        }
+        Ok(references)
+    }
+
+    fn find_placeholders(
+        &self,
+        path: &str,
+        references: &mut HashMap<String, Vec<String>>,
+    ) -> anyhow::Result<()> {
+        if self.read_link(path)?.is_some() || !self.fs.exists(path)? {
+            return Ok(());
+        }
+
+        let metadata = self.fs.metadata(path)?;
+        match metadata.file_type {
+            VfsFileType::Directory => {
+                for file in self.fs.read_dir(path)? {
+                    self.find_placeholders(&format!("{path}/{file}"), references)?;
+                }
+            }
+
+            VfsFileType::File => {
+                if self.looks_like_placeholder(path, metadata.len)? {
+                    let (_, hash) = self.read_placeholder(path)?;
+
+                    references.entry(hash).or_default().push(path.to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the chunks of a large file one after another, opening each only once the last is done.
+struct ChunksReader<'c, 'f, F: FileSystem> {
+    cache: &'c Cache<'f, F>,
+    chunks: std::vec::IntoIter<String>,
+    current: Option<Box<dyn Read + 'c>>,
+}
+
+impl<F: FileSystem> Read for ChunksReader<'_, '_, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let current = match &mut self.current {
+                Some(current) => current,
+                None => {
+                    let Some(hash) = self.chunks.next() else {
+                        return Ok(0);
+                    };
+                    let blob = format!("{}/large_files/{hash}", self.cache.cache);
+                    self.current.insert(
+                        self.cache
+                            .open_blob(&blob)
+                            .map_err(|e| std::io::Error::other(format!("{blob:?}: {e:#}")))?,
+                    )
+                }
+            };
+
+            let read = current.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            self.current = None;
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub removed: usize,
+    pub freed: u64,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub blobs: usize,
+    pub blob_bytes: u64,
+    pub placeholders: usize,
+    /// Bytes that would be stored again without dedup.
+    pub dedup_savings: u64,
+    /// The largest blobs' hashes and sizes, largest first.
+    pub largest: Vec<(String, u64)>,
+}
+
+/// Converts a native path to the `/` separated form `vfs` expects.
+fn normalize(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// Splits a Windows drive letter from a normalized path, e.g. `C:/foo` into `C` and `/foo`.
+fn split_drive(path: &str) -> Option<(&str, &str)> {
+    match path.as_bytes() {
+        [letter, b':', ..] if letter.is_ascii_alphabetic() => Some((&path[..1], &path[2..])),
+        _ => None,
+    }
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{metadata, set_permissions, Permissions};
+    use tempfile::tempdir;
+
+    #[test]
+    fn save_load_single_file() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/src").unwrap();
+        write!(fs.create_file("/src/foo.txt").unwrap(), "foo").unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["/src"]).unwrap();
+        let _ = fs.remove_file("/src/foo.txt");
+        cache.load().unwrap();
+
+        let mut foo = String::new();
+        fs.open_file("/src/foo.txt")
+            .unwrap()
+            .read_to_string(&mut foo)
+            .unwrap();
+        assert_eq!(foo, "foo");
+    }
+
+    #[test]
+    fn excluded_files_are_not_saved() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/target").unwrap();
+        fs.create_dir("/target/incremental").unwrap();
+        write!(fs.create_file("/target/foo").unwrap(), "foo").unwrap();
+        write!(fs.create_file("/target/incremental/bar").unwrap(), "bar").unwrap();
+
+        let mut excludes = GlobSetBuilder::new();
+        excludes.add(Glob::new("**/incremental/**").unwrap());
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project")
+            .with_excludes(excludes.build().unwrap());
+
+        cache.save(&["/target"]).unwrap();
+
+        assert!(fs.exists("/cache/absolute/target/foo").unwrap());
+        assert!(!fs.exists("/cache/absolute/target/incremental/bar").unwrap());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn skips_paths_that_are_not_unicode() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/src").unwrap();
+        write!(fs.create_file("/src/foo.txt").unwrap(), "foo").unwrap();
+        std::fs::write(
+            dir.path().join("src").join(OsStr::from_bytes(b"bad\xff")),
+            "bad",
+        )
+        .unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["/src"]).unwrap();
+
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("cache/absolute/src"))
+                .unwrap()
+                .map(|e| e.unwrap().file_name())
+                .collect::<Vec<_>>(),
+            ["foo.txt"]
+        );
+    }
+
+    #[test]
+    fn create_dir_all_handles_single_segments_and_the_root() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.create_dir_all("/").unwrap();
+        cache.create_dir_all("").unwrap();
+        cache.create_dir_all("foo").unwrap();
+        cache.create_dir_all("/bar/baz/").unwrap();
+
+        assert!(dir.path().join("foo").is_dir());
+        assert!(dir.path().join("bar/baz").is_dir());
+    }
+
+    #[test]
+    fn subdirectory() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/src").unwrap();
+        fs.create_dir("/src/subdir").unwrap();
+        write!(fs.create_file("/src/subdir/foo.txt").unwrap(), "foo").unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["/src"]).unwrap();
+        let _ = fs.remove_file("/src/subdir/foo.txt");
+        let _ = fs.remove_dir("/src/subdir");
+        cache.load().unwrap();
+
+        let mut foo = String::new();
+        fs.open_file("/src/subdir/foo.txt")
+            .unwrap()
+            .read_to_string(&mut foo)
+            .unwrap();
+        assert_eq!(foo, "foo");
+    }
+
+    #[test]
+    fn relative_path() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/project").unwrap();
+        fs.create_dir("/project/src").unwrap();
+        write!(fs.create_file("/project/src/foo.txt").unwrap(), "foo").unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["src"]).unwrap();
+        let _ = fs.remove_file("/project/src/foo.txt");
+        let _ = fs.remove_dir("/project/src");
+        cache.load().unwrap();
+
+        let mut foo = String::new();
+        fs.open_file("/project/src/foo.txt")
+            .unwrap()
+            .read_to_string(&mut foo)
+            .unwrap();
+        assert_eq!(foo, "foo");
+    }
+
+    #[test]
+    fn large_duplicate_files_are_only_stored_once() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/src").unwrap();
+        fs.create_file("/src/foo0.txt")
+            .unwrap()
+            .write_all(&[0; 1024])
+            .unwrap();
+        fs.create_file("/src/foo1.txt")
+            .unwrap()
+            .write_all(&[0; 1024])
+            .unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["/src"]).unwrap();
+        let _ = fs.remove_file("/src/foo0.txt");
+        let _ = fs.remove_file("/src/foo1.txt");
+
+        assert_eq!(fs.read_dir("/cache/large_files").unwrap().count(), 1);
+    }
+
+    #[test]
+    fn recovers_large_files() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/src").unwrap();
+        fs.create_file("/src/foo0.txt")
+            .unwrap()
+            .write_all(&[0; 1024])
+            .unwrap();
+        fs.create_file("/src/foo1.txt")
+            .unwrap()
+            .write_all(&[0; 1024])
+            .unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["/src"]).unwrap();
+        let _ = fs.remove_file("/src/foo0.txt");
+        let _ = fs.remove_file("/src/foo1.txt");
+        cache.load().unwrap();
+
+        let mut vec = Vec::new();
+        fs.open_file("/src/foo0.txt")
+            .unwrap()
+            .read_to_end(&mut vec)
+            .unwrap();
+        assert_eq!(vec, vec![0; 1024]);
+
+        let mut vec = Vec::new();
+        fs.open_file("/src/foo1.txt")
+            .unwrap()
+            .read_to_end(&mut vec)
+            .unwrap();
+        assert_eq!(vec, vec![0; 1024]);
+    }
+
+    #[test]
+    fn copies_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/src").unwrap();
+        write!(fs.create_file("/src/foo.exe").unwrap(), "foo").unwrap();
+
+        let file_path = dir.path().join("src/foo.exe");
+        set_permissions(&file_path, Permissions::from_mode(0o755)).unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["/src"]).unwrap();
+        let _ = fs.remove_file("/src/foo.exe");
+        cache.load().unwrap();
+
+        let metadata = metadata(&file_path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+    }
+
+    #[test]
+    fn file_that_looks_like_a_placeholder() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        let mut contents = HASHED_FILE_PREFIX.to_vec();
+        contents.extend_from_slice(blake3::hash(b"foo").to_hex().as_bytes());
+        assert_eq!(contents.len(), 77);
+
+        fs.create_dir("/src").unwrap();
+        fs.create_file("/src/fixture")
+            .unwrap()
+            .write_all(&contents)
+            .unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["/src"]).unwrap();
+        let _ = fs.remove_file("/src/fixture");
+        cache.load().unwrap();
+
+        let mut vec = Vec::new();
+        fs.open_file("/src/fixture")
+            .unwrap()
+            .read_to_end(&mut vec)
+            .unwrap();
+        assert_eq!(vec, contents);
+    }
+
+    #[test]
+    fn sha256_placeholders_record_their_algorithm() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/src").unwrap();
+        write!(fs.create_file("/src/foo.txt").unwrap(), "abc").unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project")
+            .with_dedup_threshold(0)
+            .with_hash_algorithm(HashAlgorithm::Sha256)
+            .with_verify(true);
+        cache.save(&["/src"]).unwrap();
+
+        let hash = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let read = |path: &str| std::fs::read(dir.path().join(path)).unwrap();
+        assert_eq!(
+            read("cache/absolute/src/foo.txt"),
+            format!("GENTLE HASHED\nsha256 {hash}").as_bytes()
+        );
+        assert!(fs.exists(&format!("/cache/large_files/{hash}")).unwrap());
+
+        fs.remove_file("/src/foo.txt").unwrap();
+        cache.load().unwrap();
+        assert_eq!(read("src/foo.txt"), b"abc");
+    }
+
+    #[test]
+    fn placeholders_without_an_algorithm_are_blake3() {
+        let hash = blake3::hash(b"foo").to_hex();
+
+        assert_eq!(
+            parse_placeholder(format!("GENTLE HASHED{hash}").as_bytes()),
+            Some((HashAlgorithm::Blake3, hash.as_str()))
+        );
+        assert_eq!(
+            parse_placeholder(format!("GENTLE HASHED\nsha256 {hash}").as_bytes()),
+            Some((HashAlgorithm::Sha256, hash.as_str()))
+        );
+        assert_eq!(parse_placeholder(b"GENTLE HASHED\nmd5 abc"), None);
+        assert_eq!(parse_placeholder(b"GENTLE HASHED by hand"), None);
+    }
+
+    #[test]
+    fn restores_relative_symlinks() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/src").unwrap();
+        fs.create_dir("/src/bin").unwrap();
+        write!(fs.create_file("/src/foo.txt").unwrap(), "foo").unwrap();
+        std::os::unix::fs::symlink("../foo.txt", dir.path().join("src/bin/foo")).unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["/src"]).unwrap();
+        std::fs::remove_dir_all(dir.path().join("src")).unwrap();
+        cache.load().unwrap();
+
+        let link = dir.path().join("src/bin/foo");
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            std::fs::read_link(&link).unwrap(),
+            PathBuf::from("../foo.txt")
+        );
+        assert_eq!(std::fs::read_to_string(&link).unwrap(), "foo");
+    }
+
+    #[test]
+    fn restores_empty_directories() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/src").unwrap();
+        fs.create_dir("/src/empty").unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["/src"]).unwrap();
+        fs.remove_dir("/src/empty").unwrap();
+        fs.remove_dir("/src").unwrap();
+        cache.load().unwrap();
+
+        let metadata = fs.metadata("/src/empty").unwrap();
+        assert_eq!(metadata.file_type, VfsFileType::Directory);
+    }
+
+    #[test]
+    fn compresses_large_files() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/src").unwrap();
+        fs.create_file("/src/foo.txt")
+            .unwrap()
+            .write_all(&[0; 4096])
+            .unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+
+        cache.save(&["/src"]).unwrap();
+
+        let hash = blake3::hash(&[0; 4096]);
+        let blob = format!("/cache/large_files/{hash}");
+        assert!(fs.metadata(&blob).unwrap().len < 4096);
+
+        let _ = fs.remove_file("/src/foo.txt");
+        cache.load().unwrap();
+
+        let mut vec = Vec::new();
+        fs.open_file("/src/foo.txt")
+            .unwrap()
+            .read_to_end(&mut vec)
+            .unwrap();
+        assert_eq!(vec, vec![0; 4096]);
+    }
+
+    #[test]
+    fn loads_uncompressed_blobs() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
 
-    Ok(())
-}
+        let hash = blake3::hash(&[1; 2048]);
+        fs.create_dir("/cache").unwrap();
+        fs.create_dir("/cache/large_files").unwrap();
+        fs.create_file(&format!("/cache/large_files/{hash}"))
+            .unwrap()
+            .write_all(&[1; 2048])
+            .unwrap();
+        fs.create_dir("/cache/absolute").unwrap();
+        fs.create_dir("/cache/absolute/src").unwrap();
+        let mut placeholder = fs.create_file("/cache/absolute/src/foo.txt").unwrap();
+        placeholder.write_all(HASHED_FILE_PREFIX).unwrap();
+        placeholder.write_all(hash.to_hex().as_bytes()).unwrap();
+        drop(placeholder);
+
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+        cache.load().unwrap();
 
-fn path_to_string(path: PathBuf) -> anyhow::Result<String> {
-    path.to_str()
-        .ok_or(anyhow::anyhow!("path not unicode: {path:?}"))
-        .map(|s| s.to_string())
-}
+        let mut vec = Vec::new();
+        fs.open_file("/src/foo.txt")
+            .unwrap()
+            .read_to_end(&mut vec)
+            .unwrap();
+        assert_eq!(vec, vec![1; 2048]);
+    }
 
-pub fn save(to: PathBuf) -> anyhow::Result<()> {
-    let fs = PhysicalFS::new("/");
-    let cache = Cache::new(
-        &fs,
-        &path_to_string(to)?,
-        &path_to_string(std::env::current_dir()?)?,
-    );
+    #[test]
+    fn gc_removes_orphaned_large_files() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
 
-    let cache_paths = crate::targets::targets()?
-        .into_iter()
-        .flat_map(|t| t.cache_paths())
-        .map(path_to_string)
-        .collect::<Result<HashSet<String>, _>>()?;
+        fs.create_dir("/src").unwrap();
+        fs.create_file("/src/kept.txt")
+            .unwrap()
+            .write_all(&[0; 1024])
+            .unwrap();
+        fs.create_file("/src/deleted.txt")
+            .unwrap()
+            .write_all(&[1; 1024])
+            .unwrap();
 
-    for path in cache_paths {
-        cache.save(&path)?;
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+        cache.save(&["/src"]).unwrap();
+        fs.remove_file("/cache/absolute/src/deleted.txt").unwrap();
+
+        let stats = cache.gc(None).unwrap();
+
+        assert_eq!(stats.removed, 1);
+        let kept = format!("/cache/large_files/{}", blake3::hash(&[0; 1024]));
+        let deleted = format!("/cache/large_files/{}", blake3::hash(&[1; 1024]));
+        assert!(fs.exists(&kept).unwrap());
+        assert!(!fs.exists(&deleted).unwrap());
     }
 
-    Ok(())
-}
+    #[test]
+    fn gc_evicts_least_recently_saved_over_max_size() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
 
-struct Cache<'f, F: FileSystem> {
-    fs: &'f F,
-    cache: String,
-    pwd: String,
-}
+        fs.create_dir("/old").unwrap();
+        fs.create_file("/old/foo.txt")
+            .unwrap()
+            .write_all(&[0; 1024])
+            .unwrap();
+        fs.create_dir("/new").unwrap();
+        fs.create_file("/new/foo.txt")
+            .unwrap()
+            .write_all(&[1; 1024])
+            .unwrap();
 
-impl<'f, F: FileSystem> Cache<'f, F> {
-    fn new(fs: &'f F, cache: impl AsRef<str>, pwd: impl AsRef<str>) -> Self {
-        Self {
-            fs,
-            cache: cache.as_ref().to_string(),
-            pwd: pwd.as_ref().to_string(),
-        }
-    }
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+        cache.save(&["/old"]).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        cache.save(&["/new"]).unwrap();
 
-    pub(crate) fn save(&self, path: &str) -> anyhow::Result<()> {
-        self.create_dir_all(&format!("{}/large_files", self.cache))?;
+        let new_len = fs
+            .metadata(&format!("/cache/large_files/{}", blake3::hash(&[1; 1024])))
+            .unwrap()
+            .len;
+        let stats = cache.gc(Some(new_len)).unwrap();
 
-        if path.starts_with("/") {
-            self.copy_into(path, &format!("{}/absolute{path}", self.cache))?;
-        } else {
-            self.copy_into(
-                &format!("{}/{path}", self.pwd),
-                &format!("{}/relative/{path}", self.cache),
-            )?;
-        }
-        Ok(())
+        assert_eq!(stats.removed, 1);
+        assert!(!fs.exists("/cache/absolute/old/foo.txt").unwrap());
+        assert!(fs.exists("/cache/absolute/new/foo.txt").unwrap());
     }
 
-    fn copy_into(&self, from: &str, to: &str) -> anyhow::Result<()> {
-        if !self.fs.exists(from).context("Checking file existence")? {
-            return Ok(());
-        }
+    /// `len` bytes that won't compress or chunk the same as any other `seed`'s.
+    fn noise(seed: &[u8], len: usize) -> Vec<u8> {
+        let mut noise = vec![0; len];
+        blake3::Hasher::new()
+            .update(seed)
+            .finalize_xof()
+            .fill(&mut noise);
+        noise
+    }
 
-        let metadata = self.fs.metadata(from).context("Getting file metadata")?;
-        match metadata.file_type {
-            VfsFileType::Directory => {
-                self.create_dir_all(to)?;
+    /// Saves two 8MiB files, the second differing from the first only in the middle, in chunks.
+    fn save_chunked(fs: &PhysicalFS, root: &Path) -> (Vec<u8>, Vec<u8>) {
+        let old = noise(b"build", 8 << 20);
+        let mut new = old.clone();
+        new[4 << 20..(4 << 20) + 16].copy_from_slice(&[0; 16]);
 
-                for file in self.fs.read_dir(from)? {
-                    self.copy_into(
-                        &format!("{from}/{file}"),
-                        &format!("{to}/{file}").replace("//", "/"),
-                    )?;
-                }
-                return Ok(());
-            }
+        fs.create_dir("/src").unwrap();
+        fs.create_file("/src/old.bin")
+            .unwrap()
+            .write_all(&old)
+            .unwrap();
+        fs.create_file("/src/new.bin")
+            .unwrap()
+            .write_all(&new)
+            .unwrap();
 
-            VfsFileType::File => {}
-        }
+        Cache::new(fs, root, "/cache", "/project")
+            .with_compression_level(0)
+            .with_chunk_threshold(Some(1 << 20))
+            .save(&["/src"])
+            .unwrap();
 
-        let mut from_file = self.fs.open_file(from).context("Opening {from:?}")?;
+        (old, new)
+    }
 
-        let copy_from = {
-            let mut result = from.to_string();
-            if metadata.len as usize == HASHED_FILE_PREFIX.len() + 64 {
-                let mut contents = Vec::with_capacity(metadata.len as usize);
-                from_file.read_to_end(&mut contents)?;
+    #[test]
+    fn chunked_files_share_unchanged_chunks() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+        let (old, new) = save_chunked(&fs, dir.path());
 
-                if contents.starts_with(HASHED_FILE_PREFIX) {
-                    let hash = blake3::Hash::from_hex(&contents[HASHED_FILE_PREFIX.len()..])?;
-                    result = format!("{}/large_files/{hash}", self.cache);
-                }
-            }
-            result
-        };
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project").with_verify(true);
+        let stats = cache.stats(0).unwrap();
+        assert!(stats.blobs > 4, "{stats:?}");
+        assert!(stats.blob_bytes < 10 << 20, "{stats:?}");
+        assert!(stats.dedup_savings > 6 << 20, "{stats:?}");
 
-        let copy_to = if metadata.len < DEDUPLICATE_LARGER_THAN {
-            to.to_string()
-        } else {
-            let mut hasher = blake3::Hasher::new();
-            std::io::copy(&mut from_file, &mut hasher)?;
-            let hash = hasher.finalize().to_hex();
+        fs.remove_file("/src/old.bin").unwrap();
+        fs.remove_file("/src/new.bin").unwrap();
+        cache.load().unwrap();
 
-            let mut write = self.fs.create_file(to)?;
-            write.write_all(HASHED_FILE_PREFIX)?;
-            write.write_all(hash.as_ref().as_bytes())?;
+        assert_eq!(std::fs::read(dir.path().join("src/old.bin")).unwrap(), old);
+        assert_eq!(std::fs::read(dir.path().join("src/new.bin")).unwrap(), new);
+    }
 
-            format!("{}/large_files/{hash}", self.cache)
-        };
+    #[test]
+    fn gc_removes_chunks_only_of_removed_files() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+        let (old, _) = save_chunked(&fs, dir.path());
+        let blobs = fs.read_dir("/cache/large_files").unwrap().count();
 
-        self.fs.copy_file(&copy_from, &copy_to)?;
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project").with_verify(true);
+        fs.remove_file("/cache/absolute/src/new.bin").unwrap();
+        let stats = cache.gc(None).unwrap();
 
-        Ok(())
+        // Its list of chunks and at least the one changed chunk.
+        assert!(stats.removed >= 2, "{stats:?}");
+        assert!(stats.freed < 8 << 20, "{stats:?}");
+        assert_eq!(
+            fs.read_dir("/cache/large_files").unwrap().count(),
+            blobs - stats.removed
+        );
+
+        fs.remove_file("/src/old.bin").unwrap();
+        cache.load().unwrap();
+        assert_eq!(std::fs::read(dir.path().join("src/old.bin")).unwrap(), old);
+
+        cache.gc(Some(0)).unwrap();
+        assert_eq!(fs.read_dir("/cache/large_files").unwrap().count(), 0);
     }
 
-    fn create_dir_all(&self, dir: &str) -> anyhow::Result<()> {
-        if self.fs.exists(dir)? {
-            return Ok(());
-        }
+    #[test]
+    fn load_hardlinks_files() {
+        use std::os::unix::fs::MetadataExt;
 
-        let (parent, _) = dir.rsplit_once("/").unwrap();
-        self.create_dir_all(parent)?;
-        self.fs.create_dir(dir)?;
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
 
-        Ok(())
-    }
+        fs.create_dir("/src").unwrap();
+        write!(fs.create_file("/src/foo.txt").unwrap(), "foo").unwrap();
+        fs.create_file("/src/large.bin")
+            .unwrap()
+            .write_all(&[0; 1024])
+            .unwrap();
 
-    pub(crate) fn load(&self) -> anyhow::Result<()> {
-        self.copy_into(&format!("{}/absolute", self.cache), "/")
-            .context("Loading absolute paths")?;
-        self.copy_into(&format!("{}/relative", self.cache), &self.pwd)
-            .context("Loading relative paths")?;
-        Ok(())
-    }
-}
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project").with_compression_level(0);
+        cache.save(&["/src"]).unwrap();
+        cache.load().unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let inode = |path: &str| metadata(dir.path().join(path)).unwrap().ino();
+        assert_eq!(inode("src/foo.txt"), inode("cache/absolute/src/foo.txt"));
+        let blob = format!("cache/large_files/{}", blake3::hash(&[0; 1024]));
+        assert_eq!(inode("src/large.bin"), inode(&blob));
 
-    use tempfile::tempdir;
+        cache.save(&["/src"]).unwrap();
+        let read = |path: &str| std::fs::read(dir.path().join(path)).unwrap();
+        assert_eq!(read("src/foo.txt"), b"foo");
+        assert_eq!(read("src/large.bin"), vec![0; 1024]);
+    }
 
     #[test]
-    fn save_load_single_file() {
+    fn load_copies_without_hardlinks() {
+        use std::os::unix::fs::MetadataExt;
+
         let dir = tempdir().unwrap();
         let fs = PhysicalFS::new(dir.path());
 
         fs.create_dir("/src").unwrap();
         write!(fs.create_file("/src/foo.txt").unwrap(), "foo").unwrap();
 
-        let cache = Cache::new(&fs, "/cache", "/project");
-
-        cache.save("/src").unwrap();
-        let _ = fs.remove_file("/src/foo.txt");
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project").with_hardlinks(false);
+        cache.save(&["/src"]).unwrap();
         cache.load().unwrap();
 
-        let mut foo = String::new();
-        fs.open_file("/src/foo.txt")
-            .unwrap()
-            .read_to_string(&mut foo)
-            .unwrap();
-        assert_eq!(foo, "foo");
+        let inode = |path: &str| metadata(dir.path().join(path)).unwrap().ino();
+        assert_ne!(inode("src/foo.txt"), inode("cache/absolute/src/foo.txt"));
     }
 
     #[test]
-    fn subdirectory() {
+    fn dedup_threshold_zero_dedups_every_file() {
         let dir = tempdir().unwrap();
         let fs = PhysicalFS::new(dir.path());
 
         fs.create_dir("/src").unwrap();
-        fs.create_dir("/src/subdir").unwrap();
-        write!(fs.create_file("/src/subdir/foo.txt").unwrap(), "foo").unwrap();
+        write!(fs.create_file("/src/foo.txt").unwrap(), "foo").unwrap();
+        write!(fs.create_file("/src/bar.txt").unwrap(), "foo").unwrap();
 
-        let cache = Cache::new(&fs, "/cache", "/project");
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project").with_dedup_threshold(0);
+        cache.save(&["/src"]).unwrap();
 
-        cache.save("/src").unwrap();
-        let _ = fs.remove_file("/src/subdir/foo.txt");
-        let _ = fs.remove_dir("/src/subdir");
+        assert_eq!(fs.read_dir("/cache/large_files").unwrap().count(), 1);
+
+        fs.remove_file("/src/foo.txt").unwrap();
         cache.load().unwrap();
 
         let mut foo = String::new();
-        fs.open_file("/src/subdir/foo.txt")
+        fs.open_file("/src/foo.txt")
             .unwrap()
             .read_to_string(&mut foo)
             .unwrap();
@@ -205,121 +1676,253 @@ mod tests {
     }
 
     #[test]
-    fn relative_path() {
+    fn concurrent_saves_of_one_blob_leave_no_temp_files() {
         let dir = tempdir().unwrap();
         let fs = PhysicalFS::new(dir.path());
 
-        fs.create_dir("/project").unwrap();
-        fs.create_dir("/project/src").unwrap();
-        write!(fs.create_file("/project/src/foo.txt").unwrap(), "foo").unwrap();
+        fs.create_dir("/src").unwrap();
+        for i in 0..32 {
+            fs.create_file(&format!("/src/foo{i}.txt"))
+                .unwrap()
+                .write_all(&[0; 4096])
+                .unwrap();
+        }
 
-        let cache = Cache::new(&fs, "/cache", "/project");
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+        cache.save(&["/src"]).unwrap();
 
-        cache.save("src").unwrap();
-        let _ = fs.remove_file("/project/src/foo.txt");
-        let _ = fs.remove_dir("/project/src");
+        assert_eq!(
+            fs.read_dir("/cache/large_files")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![blake3::hash(&[0; 4096]).to_string()]
+        );
+
+        fs.remove_file("/src/foo31.txt").unwrap();
         cache.load().unwrap();
 
-        let mut foo = String::new();
-        fs.open_file("/project/src/foo.txt")
+        let mut vec = Vec::new();
+        fs.open_file("/src/foo31.txt")
             .unwrap()
-            .read_to_string(&mut foo)
+            .read_to_end(&mut vec)
             .unwrap();
-        assert_eq!(foo, "foo");
+        assert_eq!(vec, vec![0; 4096]);
     }
 
     #[test]
-    fn large_duplicate_files_are_only_stored_once() {
+    fn save_replaces_truncated_blob() {
         let dir = tempdir().unwrap();
         let fs = PhysicalFS::new(dir.path());
 
         fs.create_dir("/src").unwrap();
-        fs.create_file("/src/foo0.txt")
+        fs.create_file("/src/foo.txt")
             .unwrap()
-            .write_all(&[0; 1024])
+            .write_all(&[0; 4096])
             .unwrap();
-        fs.create_file("/src/foo1.txt")
+
+        // As if an earlier save was killed partway through writing the blob.
+        let blob = format!("/cache/large_files/{}", blake3::hash(&[0; 4096]));
+        fs.create_dir("/cache").unwrap();
+        fs.create_dir("/cache/large_files").unwrap();
+        fs.create_file(&blob)
             .unwrap()
-            .write_all(&[0; 1024])
+            .write_all(COMPRESSED_BLOB_HEADER)
             .unwrap();
 
-        let cache = Cache::new(&fs, "/cache", "/project");
-
-        cache.save("/src").unwrap();
-        let _ = fs.remove_file("/src/foo0.txt");
-        let _ = fs.remove_file("/src/foo1.txt");
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project");
+        cache.save(&["/src"]).unwrap();
+        fs.remove_file("/src/foo.txt").unwrap();
+        cache.load().unwrap();
 
-        let path = VfsPath::from(fs);
-        let total_file_size = path
-            .walk_dir()
+        let mut vec = Vec::new();
+        fs.open_file("/src/foo.txt")
             .unwrap()
-            .map(|r| Ok::<u64, VfsError>(r?.metadata()?.len))
-            .sum::<Result<u64, _>>()
+            .read_to_end(&mut vec)
             .unwrap();
-
-        assert_eq!(
-            total_file_size,
-            1024 + (64 + HASHED_FILE_PREFIX.len() as u64) * 2
-        );
+        assert_eq!(vec, vec![0; 4096]);
+        assert_eq!(fs.read_dir("/cache/tmp").unwrap().count(), 0);
     }
 
     #[test]
-    fn recovers_large_files() {
+    fn verify_rejects_corrupt_blob() {
         let dir = tempdir().unwrap();
         let fs = PhysicalFS::new(dir.path());
 
         fs.create_dir("/src").unwrap();
-        fs.create_file("/src/foo0.txt")
-            .unwrap()
-            .write_all(&[0; 1024])
-            .unwrap();
-        fs.create_file("/src/foo1.txt")
+        fs.create_file("/src/foo.txt")
             .unwrap()
             .write_all(&[0; 1024])
             .unwrap();
 
-        let cache = Cache::new(&fs, "/cache", "/project");
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project").with_compression_level(0);
+        cache.save(&["/src"]).unwrap();
 
-        cache.save("/src").unwrap();
-        let _ = fs.remove_file("/src/foo0.txt");
-        let _ = fs.remove_file("/src/foo1.txt");
+        let hash = blake3::hash(&[0; 1024]);
+        let blob = dir.path().join(format!("cache/large_files/{hash}"));
+        let mut contents = std::fs::read(&blob).unwrap();
+        contents[100] ^= 1;
+        std::fs::write(&blob, contents).unwrap();
+
+        let err = cache.with_verify(true).load().unwrap_err();
+        assert!(format!("{err:#}").contains(&format!("Large file {hash} is corrupt")));
+    }
+
+    #[test]
+    fn windows_separators() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+
+        fs.create_dir("/project").unwrap();
+        fs.create_dir("/project/src").unwrap();
+        fs.create_dir("/project/src/sub").unwrap();
+        write!(fs.create_file("/project/src/sub/foo.txt").unwrap(), "foo").unwrap();
+
+        let cache = Cache::new(&fs, dir.path(), "\\cache", "\\project");
+
+        cache.save(&["src\\sub"]).unwrap();
+        assert!(fs.exists("/cache/relative/src/sub/foo.txt").unwrap());
+
+        fs.remove_file("/project/src/sub/foo.txt").unwrap();
         cache.load().unwrap();
 
-        let mut vec = Vec::new();
-        fs.open_file("/src/foo0.txt")
+        let mut foo = String::new();
+        fs.open_file("/project/src/sub/foo.txt")
             .unwrap()
-            .read_to_end(&mut vec)
+            .read_to_string(&mut foo)
             .unwrap();
-        assert_eq!(vec, vec![0; 1024]);
+        assert_eq!(foo, "foo");
+    }
 
-        let mut vec = Vec::new();
-        fs.open_file("/src/foo1.txt")
-            .unwrap()
-            .read_to_end(&mut vec)
+    #[test]
+    fn windows_drive_paths_are_saved_under_the_drive_letter() {
+        assert_eq!(split_drive("C:/Users/foo"), Some(("C", "/Users/foo")));
+        assert_eq!(split_drive("/Users/foo"), None);
+        assert_eq!(split_drive("src/foo:bar"), None);
+    }
+
+    #[test]
+    fn paths_under_home_load_under_the_loading_home() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
+        let home = |home: &str| roots(|var| (var == "HOME").then(|| home.to_string()));
+
+        fs.create_dir("/home").unwrap();
+        fs.create_dir("/home/alice").unwrap();
+        fs.create_dir("/home/alice/.cache").unwrap();
+        write!(fs.create_file("/home/alice/.cache/foo").unwrap(), "foo").unwrap();
+        fs.create_dir("/home/alice/tool").unwrap();
+        write!(fs.create_file("/home/alice/tool/bar").unwrap(), "bar").unwrap();
+
+        Cache::new(&fs, dir.path(), "/cache", "/project")
+            .with_roots(home("/home/alice"))
+            .save(&["/home/alice/.cache", "/home/alice/tool"])
             .unwrap();
-        assert_eq!(vec, vec![0; 1024]);
+        assert!(fs.exists("/cache/roots/XDG_CACHE_HOME/foo").unwrap());
+        assert!(fs.exists("/cache/roots/HOME/tool/bar").unwrap());
+        assert!(!fs.exists("/cache/absolute").unwrap());
+
+        Cache::new(&fs, dir.path(), "/cache", "/project")
+            .with_roots(home("/home/bob"))
+            .load()
+            .unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(dir.path().join(path)).unwrap();
+        assert_eq!(read("home/bob/.cache/foo"), "foo");
+        assert_eq!(read("home/bob/tool/bar"), "bar");
     }
 
     #[test]
-    fn copies_file_permissions() {
-        use std::os::unix::fs::PermissionsExt;
+    fn roots_default_under_home() {
+        let roots = roots(|var| match var {
+            "USERPROFILE" => Some(String::from("C:\\Users\\alice")),
+            "GOPATH" => Some(String::from("D:\\go\\")),
+            _ => None,
+        });
+
+        assert_eq!(
+            roots,
+            [
+                ("HOME", "C:/Users/alice"),
+                ("XDG_CACHE_HOME", "C:/Users/alice/.cache"),
+                ("CARGO_HOME", "C:/Users/alice/.cargo"),
+                ("GOPATH", "D:/go"),
+            ]
+            .map(|(name, path)| (name.to_string(), path.to_string()))
+        );
+    }
 
+    #[test]
+    fn restores_modification_times() {
         let dir = tempdir().unwrap();
         let fs = PhysicalFS::new(dir.path());
 
         fs.create_dir("/src").unwrap();
-        write!(fs.create_file("/src/foo.exe").unwrap(), "foo").unwrap();
+        write!(fs.create_file("/src/small.txt").unwrap(), "foo").unwrap();
+        fs.create_file("/src/large.bin")
+            .unwrap()
+            .write_all(&[0; 4096])
+            .unwrap();
 
-        let file_path = dir.path().join("src/foo.exe");
-        set_permissions(&file_path, Permissions::from_mode(0o755)).unwrap();
+        let modified = SystemTime::now() - std::time::Duration::from_secs(3600);
+        for file in ["src/small.txt", "src/large.bin"] {
+            std::fs::File::open(dir.path().join(file))
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+
+        for hardlinks in [true, false] {
+            let cache = Cache::new(&fs, dir.path(), "/cache", "/project")
+                .with_compression_level(0)
+                .with_hardlinks(hardlinks);
+
+            cache.save(&["/src"]).unwrap();
+            fs.remove_file("/src/small.txt").unwrap();
+            fs.remove_file("/src/large.bin").unwrap();
+            cache.load().unwrap();
+
+            for file in ["src/small.txt", "src/large.bin"] {
+                let restored = metadata(dir.path().join(file)).unwrap().modified().unwrap();
+                let difference = restored
+                    .duration_since(modified)
+                    .unwrap_or_else(|e| e.duration());
+                assert!(
+                    difference.as_secs() < 1,
+                    "{file} restored {difference:?} off"
+                );
+            }
+        }
+    }
 
-        let cache = Cache::new(&fs, "/cache", "/project");
+    #[test]
+    fn stats_counts_dedup_savings() {
+        let dir = tempdir().unwrap();
+        let fs = PhysicalFS::new(dir.path());
 
-        cache.save("/src").unwrap();
-        let _ = fs.remove_file("/src/foo.exe");
-        cache.load().unwrap();
+        fs.create_dir("/src").unwrap();
+        for i in 0..3 {
+            fs.create_file(&format!("/src/foo{i}.txt"))
+                .unwrap()
+                .write_all(&[0; 1024])
+                .unwrap();
+        }
+        fs.create_file("/src/bar.txt")
+            .unwrap()
+            .write_all(&[1; 4096])
+            .unwrap();
 
-        let metadata = metadata(&file_path).unwrap();
-        assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        let cache = Cache::new(&fs, dir.path(), "/cache", "/project").with_compression_level(0);
+        cache.save(&["/src"]).unwrap();
+
+        let stats = cache.stats(1).unwrap();
+
+        assert_eq!(stats.blobs, 2);
+        assert_eq!(stats.blob_bytes, 1024 + 4096);
+        assert_eq!(stats.placeholders, 4);
+        assert_eq!(stats.dedup_savings, 2 * 1024);
+        assert_eq!(
+            stats.largest,
+            vec![(blake3::hash(&[1; 4096]).to_string(), 4096)]
+        );
     }
 }
@@ -0,0 +1,210 @@
+use serde::*;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use gentle::target::*;
+
+#[derive(Deserialize, Default)]
+pub(crate) struct Config {
+    /// Targets matching any of these are never run, e.g. `//legacy/...`.
+    #[serde(default)]
+    skip: Vec<TargetMatcher>,
+
+    /// When non-empty, only targets matching these are run, less any in `skip`.
+    #[serde(default)]
+    only: Vec<TargetMatcher>,
+
+    /// Same as `--no-ignore`.
+    #[serde(default)]
+    pub(crate) no_ignore: bool,
+
+    /// Make a target per Go package, found with `go list`, rather than per module.
+    #[serde(default)]
+    pub(crate) go_packages: bool,
+
+    /// The `[discovery]` section.
+    #[serde(default)]
+    pub(crate) discovery: DiscoveryConfig,
+
+    /// Environment variables for the tasks of targets matching each matcher, e.g.
+    /// `[env."//backend/..."]` then `DATABASE_URL = "postgres://${DB_HOST}/test"`. `${VAR}` is
+    /// replaced with VAR from gentle's own environment. Where matchers overlap, the longest wins.
+    #[serde(default, deserialize_with = "deserialize_env")]
+    env: EnvConfig,
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct DiscoveryConfig {
+    /// Kinds of target, most preferred first, e.g. `["rust", "go", "make"]`. A directory where
+    /// several kinds are found only gets targets of the most preferred one. `make` targets are
+    /// only kept where no other kind is found, unless listed here.
+    #[serde(default)]
+    pub(crate) precedence: Vec<String>,
+
+    /// Keep every kind found in a directory even with a `precedence`.
+    #[serde(default)]
+    pub(crate) multiple: bool,
+}
+
+/// Environment variables by the matcher of the targets they're for.
+type EnvConfig = Vec<(TargetMatcher, BTreeMap<String, String>)>;
+
+impl Config {
+    pub(crate) fn includes(&self, address: &TargetAddress) -> bool {
+        (self.only.is_empty() || self.only.matches(address)) && !self.skip.matches(address)
+    }
+
+    /// The configured environment for `address`'s tasks, expanded with `var`.
+    pub(crate) fn env(
+        &self,
+        address: &TargetAddress,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> BTreeMap<String, String> {
+        self.env
+            .iter()
+            .filter(|(matcher, _)| matcher.matches(address))
+            .flat_map(|(_, vars)| vars)
+            .map(|(key, value)| (key.clone(), expand(value, &var)))
+            .collect()
+    }
+}
+
+/// Env matchers, shortest first so longer ones override them.
+fn deserialize_env<'de, D: Deserializer<'de>>(deserializer: D) -> Result<EnvConfig, D::Error> {
+    let mut env = BTreeMap::<String, BTreeMap<String, String>>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(matcher, vars)| Ok((matcher.parse().map_err(de::Error::custom)?, vars, matcher)))
+        .collect::<Result<Vec<_>, D::Error>>()?;
+    env.sort_by_key(|(_, _, matcher)| matcher.len());
+    Ok(env
+        .into_iter()
+        .map(|(matcher, vars, _)| (matcher, vars))
+        .collect())
+}
+
+/// `value` with each `${VAR}` replaced by `var("VAR")`, or nothing if that's unset.
+fn expand(value: &str, var: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&var(&rest[start + 2..start + len]).unwrap_or_default());
+        rest = &rest[start + len + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+const CONFIG_FILE_NAMES: &[&str] = &["gentle.toml", ".gentle.toml"];
+
+/// Finds the nearest config file, the way git finds `.git`.
+pub(crate) fn find_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|dir| CONFIG_FILE_NAMES.iter().map(move |name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_config_in_ancestor() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a/b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(dir.path().join("gentle.toml"), "").unwrap();
+
+        assert_eq!(find_config(&nested), Some(dir.path().join("gentle.toml")));
+
+        std::fs::write(dir.path().join("a/.gentle.toml"), "").unwrap();
+        assert_eq!(
+            find_config(&nested),
+            Some(dir.path().join("a/.gentle.toml"))
+        );
+    }
+
+    #[test]
+    fn config_only_then_skip() {
+        let config: Config = toml::from_str(
+            r#"
+            only = ["//a:rust_crate", "//b:rust_crate"]
+            skip = ["//b:rust_crate", "//c:rust_crate"]
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.includes(&"//a:rust_crate".parse().unwrap()));
+        assert!(!config.includes(&"//b:rust_crate".parse().unwrap()));
+        assert!(!config.includes(&"//c:rust_crate".parse().unwrap()));
+        assert!(!config.includes(&"//d:rust_crate".parse().unwrap()));
+    }
+
+    #[test]
+    fn config_without_only_includes_everything_not_skipped() {
+        let config: Config = toml::from_str(r#"skip = ["//b:rust_crate"]"#).unwrap();
+
+        assert!(config.includes(&"//a:rust_crate".parse().unwrap()));
+        assert!(!config.includes(&"//b:rust_crate".parse().unwrap()));
+    }
+
+    #[test]
+    fn config_skip_matchers() {
+        let config: Config = toml::from_str(r#"skip = ["//legacy/...", "//tools:go_*"]"#).unwrap();
+
+        assert!(!config.includes(&"//legacy:rust_crate".parse().unwrap()));
+        assert!(!config.includes(&"//legacy/old:python".parse().unwrap()));
+        assert!(!config.includes(&"//tools:go_mod".parse().unwrap()));
+        assert!(config.includes(&"//tools:rust_crate".parse().unwrap()));
+        assert!(config.includes(&"//legacyish:python".parse().unwrap()));
+    }
+
+    #[test]
+    fn config_env_for_matching_targets() {
+        let config: Config = toml::from_str(
+            r#"
+            [env."//backend/..."]
+            DATABASE_URL = "postgres://${DB_HOST}/test"
+            RUST_LOG = "info"
+
+            [env."//backend/api:rust_crate"]
+            RUST_LOG = "debug"
+            "#,
+        )
+        .unwrap();
+        let var = |name: &str| (name == "DB_HOST").then(|| String::from("localhost"));
+
+        assert_eq!(
+            config.env(&"//backend/api:rust_crate".parse().unwrap(), var),
+            [
+                (
+                    String::from("DATABASE_URL"),
+                    String::from("postgres://localhost/test")
+                ),
+                (String::from("RUST_LOG"), String::from("debug")),
+            ]
+            .into()
+        );
+        assert!(config
+            .env(&"//frontend:node".parse().unwrap(), var)
+            .is_empty());
+        assert!(toml::from_str::<Config>("[env.backend]\nA = \"1\"").is_err());
+    }
+
+    #[test]
+    fn expand_replaces_set_and_unset_variables() {
+        let var = |name: &str| (name == "HOME").then(|| String::from("/home/me"));
+
+        assert_eq!(expand("${HOME}/x:${UNSET}:${", var), "/home/me/x::${");
+    }
+
+    #[test]
+    fn config_rejects_invalid_matchers() {
+        assert!(toml::from_str::<Config>(r#"skip = ["legacy"]"#).is_err());
+    }
+}
@@ -0,0 +1,436 @@
+//! The `gentle` command line.
+
+use serde::*;
+use std::path::*;
+use structopt::*;
+
+use crate::cache;
+use gentle::{target::*, targets};
+
+mod config;
+mod plan;
+mod progress;
+mod run;
+
+use config::{find_config, Config};
+use plan::select_targets;
+use run::*;
+
+/// The commit and time this binary was built from, to tell builds of the same version apart.
+const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("GENTLE_COMMIT"),
+    ", built ",
+    env!("GENTLE_BUILT"),
+    ")"
+);
+
+/// Exit code when arguments or config are invalid. Failed tasks, and other errors, exit with 1.
+pub const USAGE_ERROR: i32 = 2;
+
+#[derive(StructOpt)]
+#[structopt(
+    version = VERSION,
+    after_help = "Exits with 1 if any task fails and 2 if arguments or config are invalid."
+)]
+pub struct Options {
+    /// Defaults to the first `gentle.toml` or `.gentle.toml` in the working directory or its
+    /// ancestors.
+    #[structopt(long)]
+    config_file: Option<PathBuf>,
+
+    /// Maximum number of tasks to run in parallel. Defaults to the number of CPUs.
+    #[structopt(short, long)]
+    jobs: Option<usize>,
+
+    /// Maximum total weight of tasks to run in parallel, where a typical target weighs 1 and
+    /// large ones more. Overrides `--jobs`.
+    #[structopt(long)]
+    load: Option<usize>,
+
+    /// Write each task's output to a file in this directory as it runs.
+    #[structopt(long)]
+    log_dir: Option<PathBuf>,
+
+    /// Also discover targets in gitignored and hidden directories. Paths in `.gentleignore` files
+    /// are still skipped.
+    #[structopt(long)]
+    no_ignore: bool,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+pub enum Command {
+    CacheLoad {
+        from: PathBuf,
+
+        /// Always copy files out of the cache instead of hardlinking them.
+        #[structopt(long)]
+        no_hardlink: bool,
+
+        /// Check every large file against its hash, failing if any are corrupt.
+        #[structopt(long)]
+        verify: bool,
+    },
+    CacheSave {
+        to: PathBuf,
+
+        /// zstd level used to compress large files, or 0 to store them uncompressed.
+        #[structopt(long, default_value = "3")]
+        compression_level: i32,
+
+        /// Store files at least this many bytes once, shared between every copy. 0 dedups every
+        /// file.
+        #[structopt(long, default_value = "1024")]
+        dedup_threshold: u64,
+
+        /// Split files at least this many bytes into content-defined chunks, stored once each, so
+        /// files that change slightly between saves share most of their storage.
+        #[structopt(long)]
+        chunk_threshold: Option<u64>,
+
+        /// What to name large files by, `blake3` or `sha256`. Loading handles either.
+        #[structopt(long, default_value = "blake3")]
+        hash_algorithm: cache::HashAlgorithm,
+    },
+
+    /// Delete large files in the cache that nothing refers to any more.
+    CacheGc {
+        dir: PathBuf,
+
+        /// Then evict the least recently saved large files until they total at most this many
+        /// bytes.
+        #[structopt(long)]
+        max_size: Option<u64>,
+    },
+
+    /// Print how much space the cache uses and how much deduplication saves.
+    CacheStats {
+        dir: PathBuf,
+
+        /// Number of the largest files to list.
+        #[structopt(long, default_value = "10")]
+        top: usize,
+    },
+
+    /// Print the address of every target that would be run, one per line.
+    List {
+        /// Print a JSON array of addresses instead.
+        #[structopt(long)]
+        json: bool,
+
+        /// Only list targets matching these, e.g. `//backend/...`.
+        matchers: Vec<TargetMatcher>,
+    },
+
+    /// Print a JSON array describing every target: its address, kind, path, cache paths and
+    /// the matchers it depends on.
+    Metadata {
+        /// Only describe targets matching these, e.g. `//backend/...`.
+        matchers: Vec<TargetMatcher>,
+    },
+
+    /// Delete the cache paths, like `target/`, of every target.
+    Clean {
+        /// Print what would be deleted without deleting it.
+        #[structopt(long)]
+        dry_run: bool,
+
+        /// Also delete caches outside the project, like the Go build cache, which other projects
+        /// share.
+        #[structopt(long)]
+        include_shared: bool,
+
+        /// Only clean targets matching these, e.g. `//backend/...`.
+        matchers: Vec<TargetMatcher>,
+    },
+
+    /// Build and run one executable target in the foreground, e.g.
+    /// `gentle run //tools/gen:rust_crate -- --flag`.
+    Run {
+        /// Must match exactly one target.
+        matcher: TargetMatcher,
+
+        /// Passed on to the program.
+        #[structopt(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Write a completion script for this shell to stdout, e.g. `gentle completions zsh > _gentle`.
+    #[structopt(setting = clap::AppSettings::Hidden)]
+    Completions {
+        #[structopt(possible_values = &clap::Shell::variants(), case_insensitive = true)]
+        shell: clap::Shell,
+    },
+
+    #[structopt(flatten)]
+    Action(ActionCommand),
+}
+
+pub(crate) fn exit_usage(error: impl std::fmt::Debug) -> ! {
+    eprintln!("Error: {error:?}");
+    std::process::exit(USAGE_ERROR)
+}
+
+/// Runs the command `options` parsed from the arguments.
+pub fn run(options: Options) -> anyhow::Result<()> {
+    let config_file = match &options.config_file {
+        Some(path) => Some(path.clone()),
+        None => find_config(&std::env::current_dir()?),
+    };
+    let config = if let Some(file) = config_file.and_then(|path| std::fs::read(path).ok()) {
+        toml::from_slice(&file).unwrap_or_else(|e| exit_usage(e))
+    } else {
+        Config::default()
+    };
+    ctrlc::set_handler(|| {
+        targets::kill_running();
+        std::process::exit(130);
+    })?;
+
+    let discovery = targets::Discovery {
+        no_ignore: options.no_ignore || config.no_ignore,
+        go_packages: config.go_packages,
+        precedence: config.discovery.precedence.clone(),
+        multiple: config.discovery.multiple,
+    };
+
+    match options.command {
+        Command::Action(command) => run_actions(
+            command,
+            &config,
+            &discovery,
+            options.jobs,
+            options.load,
+            options.log_dir.as_deref(),
+        )?,
+
+        Command::Run { matcher, args } => {
+            let mut targets =
+                select_targets(&config, &discovery, std::slice::from_ref(&matcher), &[])?;
+            if targets.len() != 1 {
+                let addresses = targets.iter().map(|t| format!("\n  {}", t.address()));
+                exit_usage(anyhow::anyhow!(
+                    "{matcher} must match exactly one target, but matches {}{}",
+                    targets.len(),
+                    addresses.collect::<String>()
+                ));
+            }
+            targets.remove(0).perform_run(&args)?;
+        }
+
+        Command::List { json, mut matchers } => {
+            if matchers.is_empty() {
+                matchers.push("//...".parse()?);
+            }
+
+            let addresses = select_targets(&config, &discovery, &matchers, &[])?
+                .into_iter()
+                .map(|t| t.address().to_string())
+                .collect::<Vec<_>>();
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&addresses)?);
+            } else {
+                for address in addresses {
+                    println!("{address}");
+                }
+            }
+        }
+
+        Command::Metadata { mut matchers } => {
+            if matchers.is_empty() {
+                matchers.push("//...".parse()?);
+            }
+
+            let metadata = select_targets(&config, &discovery, &matchers, &[])?
+                .iter()
+                .map(|t| TargetMetadata::new(t.as_ref()))
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::to_string_pretty(&metadata)?);
+        }
+
+        Command::Clean {
+            dry_run,
+            include_shared,
+            mut matchers,
+        } => {
+            if matchers.is_empty() {
+                matchers.push("//...".parse()?);
+            }
+
+            let root = std::env::current_dir()?;
+            let paths = select_targets(&config, &discovery, &matchers, &[])?
+                .into_iter()
+                .flat_map(|t| t.cache_paths())
+                .collect::<std::collections::BTreeSet<_>>();
+
+            for path in paths {
+                if !path.exists() {
+                    continue;
+                }
+                if is_shared(&path, &root) && !include_shared {
+                    eprintln!(
+                        "Keeping {}, it's shared with other projects (--include-shared deletes it)",
+                        path.display()
+                    );
+                    continue;
+                }
+
+                println!("{}", path.display());
+                if dry_run {
+                    continue;
+                }
+                if path.is_dir() {
+                    std::fs::remove_dir_all(&path)
+                } else {
+                    std::fs::remove_file(&path)
+                }
+                .map_err(|e| anyhow::anyhow!("removing {}: {e}", path.display()))?;
+            }
+        }
+
+        Command::Completions { shell } => {
+            Options::clap().gen_completions_to("gentle", shell, &mut std::io::stdout());
+        }
+
+        Command::CacheLoad {
+            from,
+            no_hardlink,
+            verify,
+        } => cache::load(from, !no_hardlink, verify)?,
+        Command::CacheSave {
+            to,
+            compression_level,
+            dedup_threshold,
+            chunk_threshold,
+            hash_algorithm,
+        } => cache::save(
+            to,
+            compression_level,
+            dedup_threshold,
+            chunk_threshold,
+            hash_algorithm,
+            &discovery,
+        )?,
+        Command::CacheGc { dir, max_size } => {
+            let stats = cache::gc(dir, max_size)?;
+            eprintln!(
+                "Removed {} large files, freeing {} bytes",
+                stats.removed, stats.freed
+            );
+        }
+        Command::CacheStats { dir, top } => {
+            let stats = cache::stats(dir, top)?;
+            println!("Large files: {} ({} bytes)", stats.blobs, stats.blob_bytes);
+            println!("Placeholders: {}", stats.placeholders);
+            println!("Saved by dedup: {} bytes", stats.dedup_savings);
+
+            if !stats.largest.is_empty() {
+                println!("Largest:");
+                for (hash, len) in stats.largest {
+                    println!("  {len} {hash}");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A target as `gentle metadata` describes it. Fields are only ever added, so scripts can rely
+/// on these.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct TargetMetadata {
+    address: String,
+    kind: String,
+    path: PathBuf,
+    cache_paths: Vec<PathBuf>,
+    depends_on: Vec<String>,
+}
+
+impl TargetMetadata {
+    fn new(target: &dyn targets::Target) -> Self {
+        let mut cache_paths = target.cache_paths().into_iter().collect::<Vec<_>>();
+        cache_paths.sort();
+
+        TargetMetadata {
+            address: target.address().to_string(),
+            kind: target.kind(),
+            path: target.path().to_path_buf(),
+            cache_paths,
+            depends_on: target.depends_on().iter().map(|m| m.to_string()).collect(),
+        }
+    }
+}
+
+/// Whether `path` is outside the project at `root`, so other projects may be using it.
+fn is_shared(path: &Path, root: &Path) -> bool {
+    path.is_absolute() && !path.starts_with(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) struct FakeTarget {
+        pub(super) address: &'static str,
+        pub(super) depends_on: Vec<&'static str>,
+    }
+
+    impl std::fmt::Display for FakeTarget {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.address)
+        }
+    }
+
+    impl targets::Target for FakeTarget {
+        fn address(&self) -> TargetAddress {
+            self.address.parse().unwrap()
+        }
+
+        fn path(&self) -> &Path {
+            let (package, _) = self.address[2..].split_once(':').unwrap();
+            Path::new(package)
+        }
+
+        fn perform_test(&self, _: &targets::TaskContext) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn depends_on(&self) -> Vec<TargetMatcher> {
+            self.depends_on.iter().map(|m| m.parse().unwrap()).collect()
+        }
+    }
+
+    #[test]
+    fn only_caches_outside_the_project_are_shared() {
+        let root = Path::new("/work/project");
+
+        assert!(!is_shared(Path::new("./r/target"), root));
+        assert!(!is_shared(Path::new("/work/project/.cache"), root));
+        assert!(is_shared(Path::new("/home/me/.cache/go-build"), root));
+    }
+
+    #[test]
+    fn metadata_describes_the_target() {
+        let target = FakeTarget {
+            address: "//app:rust_crate[serde]",
+            depends_on: vec!["//lib/..."],
+        };
+
+        assert_eq!(
+            TargetMetadata::new(&target),
+            TargetMetadata {
+                address: String::from("//app:rust_crate[serde]"),
+                kind: String::from("rust_crate"),
+                path: PathBuf::from("app"),
+                cache_paths: Vec::new(),
+                depends_on: vec![String::from("//lib/...")],
+            }
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::{collections::HashSet, path::PathBuf, sync::Arc};
+
+use super::{config::Config, run::Action};
+use crate::watch;
+use gentle::{target::*, targets};
+
+pub(crate) fn select_targets(
+    config: &Config,
+    discovery: &targets::Discovery,
+    matchers: &[TargetMatcher],
+    excludes: &[TargetMatcher],
+) -> anyhow::Result<Vec<Box<dyn targets::Target>>> {
+    Ok(targets::targets(discovery)?
+        .into_iter()
+        .filter(|t| selects(config, matchers, excludes, &t.address()))
+        .collect())
+}
+
+fn selects(
+    config: &Config,
+    matchers: &[TargetMatcher],
+    excludes: &[TargetMatcher],
+    address: &TargetAddress,
+) -> bool {
+    matchers.matches(address) && !excludes.matches(address) && config.includes(address)
+}
+
+/// Files changed between `since` and the working tree, relative to the working directory.
+pub(crate) fn changed_since(since: &str) -> anyhow::Result<HashSet<PathBuf>> {
+    let out = std::process::Command::new("git")
+        .args(["diff", "--name-only", "--relative", since])
+        .output()?;
+    anyhow::ensure!(
+        out.status.success(),
+        "git diff failed: {}",
+        String::from_utf8_lossy(&out.stderr).trim()
+    );
+
+    Ok(String::from_utf8(out.stdout)?
+        .lines()
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// The targets containing any of the `changed` files, and every target depending on those.
+pub(crate) fn touched(
+    targets: &[Arc<dyn targets::Target>],
+    changed: &HashSet<PathBuf>,
+) -> Vec<Arc<dyn targets::Target>> {
+    let mut touched = targets
+        .iter()
+        .map(|t| watch::affects(&t.address().package, changed))
+        .collect::<Vec<_>>();
+
+    // Until nothing new depends on a touched target.
+    loop {
+        let addresses = targets
+            .iter()
+            .zip(&touched)
+            .filter(|(_, &touched)| touched)
+            .map(|(t, _)| t.address())
+            .collect::<Vec<_>>();
+        let mut added = false;
+        for (target, touched) in targets.iter().zip(&mut touched) {
+            let depends_on = target.depends_on();
+            if !*touched && addresses.iter().any(|a| depends_on.matches(a)) {
+                *touched = true;
+                added = true;
+            }
+        }
+        if !added {
+            break;
+        }
+    }
+
+    targets
+        .iter()
+        .zip(touched)
+        .filter(|(_, touched)| *touched)
+        .map(|(t, _)| Arc::clone(t))
+        .collect()
+}
+
+/// For each task, the indices of the tasks for the same action on the targets it depends on.
+pub(crate) fn task_dependencies(
+    tasks: &[(String, Action, Arc<dyn targets::Target>)],
+) -> Vec<Vec<usize>> {
+    tasks
+        .iter()
+        .map(|(_, action, target)| {
+            let depends_on = target.depends_on();
+            if depends_on.is_empty() {
+                return Vec::new();
+            }
+
+            tasks
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, other_action, other))| {
+                    other_action == action
+                        && other.address() != target.address()
+                        && depends_on.matches(&other.address())
+                })
+                .map(|(i, _)| i)
+                .collect()
+        })
+        .collect()
+}
+
+/// Indices ordered so every task comes after its dependencies, or the tasks forming a cycle.
+pub(crate) fn dependency_order(dependencies: &[Vec<usize>]) -> Result<Vec<usize>, Vec<usize>> {
+    fn visit(
+        task: usize,
+        dependencies: &[Vec<usize>],
+        path: &mut Vec<usize>,
+        visited: &mut [bool],
+        order: &mut Vec<usize>,
+    ) -> Result<(), Vec<usize>> {
+        if let Some(start) = path.iter().position(|&t| t == task) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(task);
+            return Err(cycle);
+        }
+        if visited[task] {
+            return Ok(());
+        }
+
+        path.push(task);
+        for &dependency in &dependencies[task] {
+            visit(dependency, dependencies, path, visited, order)?;
+        }
+        path.pop();
+
+        visited[task] = true;
+        order.push(task);
+        Ok(())
+    }
+
+    let mut visited = vec![false; dependencies.len()];
+    let mut order = Vec::with_capacity(dependencies.len());
+    for task in 0..dependencies.len() {
+        visit(
+            task,
+            dependencies,
+            &mut Vec::new(),
+            &mut visited,
+            &mut order,
+        )?;
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::{run::ActionCommand, tests::FakeTarget, Command, Options};
+    use structopt::StructOpt;
+
+    #[test]
+    fn touched_targets_and_their_dependents() {
+        let target = |address, depends_on| {
+            Arc::new(FakeTarget {
+                address,
+                depends_on,
+            }) as Arc<dyn targets::Target>
+        };
+        let targets = [
+            target("//lib:rust_crate", vec![]),
+            target("//app:rust_crate", vec!["//lib:rust_crate"]),
+            target("//e2e:node", vec!["//app/..."]),
+            target("//other:python", vec![]),
+        ];
+        let changed = [PathBuf::from("lib/src/lib.rs"), PathBuf::from("README.md")].into();
+
+        let addresses = touched(&targets, &changed)
+            .iter()
+            .map(|t| t.address().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            addresses,
+            ["//lib:rust_crate", "//app:rust_crate", "//e2e:node"]
+        );
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        let dependencies = vec![vec![2], vec![], vec![1]];
+
+        assert_eq!(dependency_order(&dependencies), Ok(vec![1, 2, 0]));
+    }
+
+    #[test]
+    fn dependency_cycle_is_reported() {
+        let dependencies = vec![vec![], vec![2], vec![3], vec![1]];
+
+        assert_eq!(dependency_order(&dependencies), Err(vec![1, 2, 3, 1]));
+    }
+
+    #[test]
+    fn excludes_win_over_matchers() {
+        let options = Options::from_iter_safe([
+            "gentle",
+            "test",
+            "//...",
+            "--exclude",
+            "//legacy/...",
+            "--exclude",
+            "//foo:slow_crate",
+        ])
+        .unwrap();
+        let Command::Action(ActionCommand::Test(args)) = options.command else {
+            panic!("not a test command");
+        };
+        let selected = |address: &str| {
+            let matchers = ["//...".parse().unwrap()];
+            selects(
+                &Config::default(),
+                &matchers,
+                &args.exclude,
+                &address.parse().unwrap(),
+            )
+        };
+
+        assert!(selected("//foo:rust_crate"));
+        assert!(!selected("//foo:slow_crate"));
+        assert!(!selected("//legacy/old:python"));
+    }
+}
@@ -0,0 +1,697 @@
+use indicatif::*;
+use is_terminal::*;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::Write,
+    path::*,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use gentle::multi_runner::*;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Progress {
+    Auto,
+    Term,
+    Ci,
+    Github,
+    Json,
+    Null,
+}
+
+impl Progress {
+    pub(crate) fn resolve(self) -> Progress {
+        if self != Progress::Auto {
+            return self;
+        }
+
+        if std::env::var("GENTLE_JSON") == Ok(String::from("1")) {
+            Progress::Json
+        } else if std::env::var("GITHUB_ACTIONS") == Ok(String::from("true")) {
+            Progress::Github
+        } else if detect_ci() {
+            Progress::Ci
+        } else if std::io::stderr().is_terminal() {
+            Progress::Term
+        } else {
+            Progress::Null
+        }
+    }
+}
+
+/// Variables set by CI providers that don't (always) set `CI`.
+const CI_VARIABLES: &[&str] = &[
+    "GITHUB_ACTIONS",
+    "GITLAB_CI",
+    "BUILDKITE",
+    "CIRCLECI",
+    "TRAVIS",
+    "JENKINS_URL",
+    "TEAMCITY_VERSION",
+    "TF_BUILD",
+    "APPVEYOR",
+    "BITBUCKET_BUILD_NUMBER",
+    "CODEBUILD_BUILD_ID",
+    "DRONE",
+];
+
+fn detect_ci() -> bool {
+    detect_ci_in(|name| std::env::var_os(name))
+}
+
+fn detect_ci_in(var: impl Fn(&str) -> Option<std::ffi::OsString>) -> bool {
+    let set = |name: &str| var(name).is_some_and(|value| !value.is_empty());
+    set("CI") || CI_VARIABLES.iter().any(|&name| set(name))
+}
+
+impl std::str::FromStr for Progress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Progress::Auto),
+            "term" => Ok(Progress::Term),
+            "ci" => Ok(Progress::Ci),
+            "github" => Ok(Progress::Github),
+            "json" => Ok(Progress::Json),
+            "null" => Ok(Progress::Null),
+            _ => Err(anyhow::anyhow!("unknown progress: {s:?}")),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OutputStyle {
+    Errors,
+    Grouped,
+}
+
+impl std::str::FromStr for OutputStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "errors" => Ok(OutputStyle::Errors),
+            "grouped" => Ok(OutputStyle::Grouped),
+            _ => Err(anyhow::anyhow!("unknown output style: {s:?}")),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    pub(crate) fn enabled(self) -> bool {
+        match self {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => {
+                // https://no-color.org: set to anything non-empty disables color.
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+                !no_color && std::io::stderr().is_terminal()
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            _ => Err(anyhow::anyhow!("unknown color: {s:?}")),
+        }
+    }
+}
+
+pub(crate) struct TermProgress {
+    multi: MultiProgress,
+    /// Counts finished tasks, above the spinners of running ones.
+    overall: ProgressBar,
+    bars: Vec<(ProgressBar, Option<String>)>,
+    color: bool,
+}
+
+impl TermProgress {
+    pub(crate) fn new(total: usize, color: bool) -> Self {
+        let multi = MultiProgress::new();
+        let overall = multi.add(ProgressBar::new(total as u64));
+        let template = if color {
+            "[{bar:40.green}] {pos}/{len} {elapsed}"
+        } else {
+            "[{bar:40}] {pos}/{len} {elapsed}"
+        };
+        overall.set_style(
+            ProgressStyle::with_template(template)
+                .expect("valid template")
+                .progress_chars("=> "),
+        );
+
+        TermProgress {
+            multi,
+            overall,
+            bars: Default::default(),
+            color,
+        }
+    }
+}
+
+impl Drop for TermProgress {
+    fn drop(&mut self) {
+        self.overall.finish_and_clear();
+        for (bar, _) in &self.bars {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+impl ProgressListener for TermProgress {
+    fn on_start(&mut self, name: &str) {
+        for (bar, running) in &mut self.bars {
+            if running.is_some() {
+                continue;
+            }
+
+            bar.set_message(name.to_string());
+            bar.reset();
+            *running = Some(name.to_string());
+            return;
+        }
+
+        let p = self.multi.add(ProgressBar::new_spinner());
+        p.set_message(name.to_string());
+        p.enable_steady_tick(Duration::from_millis(50));
+
+        self.bars.push((p, Some(name.to_string())));
+    }
+
+    fn on_retry(&mut self, name: &str, attempt: usize, attempts: usize) {
+        if let Some((bar, _)) = self
+            .bars
+            .iter()
+            .find(|(_, r)| r.as_ref() == Some(&name.to_string()))
+        {
+            bar.set_message(format!("{name} (attempt {attempt}/{attempts})"));
+        }
+    }
+
+    fn on_finish(&mut self, name: &str, passed: bool) {
+        let (bar, running) = self
+            .bars
+            .iter_mut()
+            .find(|(_, r)| r.as_ref() == Some(&name.to_string()))
+            .expect("called on_finish without on_start");
+
+        *running = None;
+        bar.set_message("");
+        self.overall.inc(1);
+        bar.finish();
+
+        // Above the bars, so failures stay visible while the rest run.
+        if !passed {
+            let mark = if self.color {
+                "\x1b[31m✗\x1b[0m"
+            } else {
+                "✗"
+            };
+            let _ = self.multi.println(format!("{mark} {name}"));
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ContinuousIntegrationProgress {
+    total: usize,
+    running: HashMap<String, Instant>,
+    queued: usize,
+    finished: usize,
+}
+
+impl ContinuousIntegrationProgress {
+    pub(crate) fn new(total: usize) -> Self {
+        eprintln!("Running {total} tasks");
+
+        ContinuousIntegrationProgress {
+            total,
+            running: Default::default(),
+            queued: total,
+            finished: 0,
+        }
+    }
+
+    fn log_status(&self) {
+        eprintln!(
+            "Running {}, queued {}, finished {} / {}",
+            self.running.len(),
+            self.queued,
+            self.finished,
+            self.total
+        );
+        for (name, started) in &self.running {
+            eprintln!(
+                "  {name}: {}",
+                humantime::format_duration(started.elapsed())
+            );
+        }
+    }
+}
+
+impl ProgressListener for ContinuousIntegrationProgress {
+    fn on_start(&mut self, name: &str) {
+        eprintln!("Starting {name}");
+        self.running.insert(name.to_string(), Instant::now());
+
+        self.log_status();
+    }
+
+    fn on_finish(&mut self, name: &str, passed: bool) {
+        let started_at = self
+            .running
+            .remove(name)
+            .expect("called on_finish without on_start");
+        let took = humantime::format_duration(started_at.elapsed());
+        if passed {
+            eprintln!("Finished {name} in {took}");
+        } else {
+            eprintln!("Failed {name} after {took}");
+        }
+
+        self.finished += 1;
+
+        self.log_status();
+    }
+
+    fn on_retry(&mut self, name: &str, attempt: usize, attempts: usize) {
+        eprintln!("Retrying {name} (attempt {attempt}/{attempts})");
+    }
+
+    fn on_queued(&mut self, queued: usize) {
+        self.queued = queued;
+    }
+
+    fn on_error(&mut self, name: &str, error: &dyn Display) {
+        // Only the first line, the full error is reported once everything finishes.
+        let error = error.to_string();
+        let first_line = error.lines().find(|l| !l.trim().is_empty());
+        let first_line = first_line.unwrap_or_default();
+        eprintln!("FAILED {name}: {first_line}");
+    }
+}
+
+/// Logs like `ContinuousIntegrationProgress`, and also writes GitHub Actions workflow commands
+/// so failures show up as annotations with their output in a collapsible group.
+pub(crate) struct GithubProgress<W: Write> {
+    log: ContinuousIntegrationProgress,
+    out: W,
+}
+
+impl<W: Write> GithubProgress<W> {
+    pub(crate) fn new(log: ContinuousIntegrationProgress, out: W) -> Self {
+        GithubProgress { log, out }
+    }
+}
+
+/// Escapes workflow command data, with `property` also escaping what separates properties.
+fn github_escape(s: &str, property: bool) -> String {
+    let escaped = s
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    if property {
+        escaped.replace(':', "%3A").replace(',', "%2C")
+    } else {
+        escaped
+    }
+}
+
+impl<W: Write> ProgressListener for GithubProgress<W> {
+    fn on_start(&mut self, name: &str) {
+        self.log.on_start(name);
+    }
+
+    fn on_finish(&mut self, name: &str, passed: bool) {
+        self.log.on_finish(name, passed);
+    }
+
+    fn on_retry(&mut self, name: &str, attempt: usize, attempts: usize) {
+        self.log.on_retry(name, attempt, attempts);
+    }
+
+    fn on_queued(&mut self, queued: usize) {
+        self.log.on_queued(queued);
+    }
+
+    fn on_error(&mut self, name: &str, error: &dyn Display) {
+        let error = error.to_string();
+        let address = name.split_once(' ').map_or(name, |(_, address)| address);
+        let message = error.lines().find(|l| !l.trim().is_empty());
+
+        let _ = writeln!(self.out, "::group::{name}");
+        let _ = writeln!(self.out, "{}", error.trim_end());
+        let _ = writeln!(self.out, "::endgroup::");
+        let _ = writeln!(
+            self.out,
+            "::error title={}::{}",
+            github_escape(address, true),
+            github_escape(message.unwrap_or("failed"), false),
+        );
+        let _ = self.out.flush();
+    }
+}
+
+/// Writes a JSON object per event, one per line, for other programs to follow.
+pub(crate) struct JsonProgress<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonProgress<W> {
+    pub(crate) fn new(out: W) -> Self {
+        JsonProgress { out }
+    }
+
+    fn event(&mut self, mut event: serde_json::Value) {
+        let ts = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        event["ts"] = ts.into();
+
+        let _ = writeln!(self.out, "{event}");
+        let _ = self.out.flush();
+    }
+}
+
+impl<W: Write> ProgressListener for JsonProgress<W> {
+    fn on_start(&mut self, name: &str) {
+        self.event(serde_json::json!({ "event": "start", "name": name }));
+    }
+
+    fn on_finish(&mut self, name: &str, passed: bool) {
+        self.event(serde_json::json!({ "event": "finish", "name": name, "passed": passed }));
+    }
+
+    fn on_retry(&mut self, name: &str, attempt: usize, attempts: usize) {
+        self.event(serde_json::json!({
+            "event": "retry",
+            "name": name,
+            "attempt": attempt,
+            "attempts": attempts,
+        }));
+    }
+
+    fn on_error(&mut self, name: &str, error: &dyn Display) {
+        self.event(serde_json::json!({
+            "event": "error",
+            "name": name,
+            "error": error.to_string(),
+        }));
+    }
+}
+
+/// Reports progress to `inner`, and keeps the current state of the run in a JSON file.
+pub(crate) struct StatusFileProgress<P> {
+    inner: P,
+    path: PathBuf,
+    total: usize,
+    running: Vec<String>,
+    finished: usize,
+}
+
+impl<P: ProgressListener> StatusFileProgress<P> {
+    pub(crate) fn new(inner: P, path: &Path, total: usize) -> Self {
+        let progress = StatusFileProgress {
+            inner,
+            path: path.to_path_buf(),
+            total,
+            running: Vec::new(),
+            finished: 0,
+        };
+        progress.write();
+        progress
+    }
+
+    /// Replaces the file by renaming a new one over it, so readers never see a partial write.
+    fn write(&self) {
+        let status = serde_json::json!({
+            "running": self.running,
+            "finished": self.finished,
+            "total": self.total,
+        });
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let written = std::fs::write(&tmp, status.to_string())
+            .and_then(|()| std::fs::rename(&tmp, &self.path));
+        if let Err(e) = written {
+            eprintln!("Failed to write {}: {e}", self.path.display());
+        }
+    }
+}
+
+impl<P: ProgressListener> ProgressListener for StatusFileProgress<P> {
+    fn on_start(&mut self, name: &str) {
+        self.inner.on_start(name);
+        self.running.push(name.to_string());
+        self.write();
+    }
+
+    fn on_finish(&mut self, name: &str, passed: bool) {
+        self.inner.on_finish(name, passed);
+        self.running.retain(|running| running != name);
+        self.finished += 1;
+        self.write();
+    }
+
+    fn on_retry(&mut self, name: &str, attempt: usize, attempts: usize) {
+        self.inner.on_retry(name, attempt, attempts);
+    }
+
+    fn on_error(&mut self, name: &str, error: &dyn Display) {
+        self.inner.on_error(name, error);
+    }
+
+    fn on_queued(&mut self, queued: usize) {
+        self.inner.on_queued(queued);
+    }
+}
+
+/// Reports progress to `inner`, and prints each task's output, collected in its buffer while it
+/// ran, between a header and footer once it finishes.
+pub(crate) struct GroupedOutput<P, W: Write> {
+    inner: P,
+    buffers: HashMap<String, Arc<std::sync::Mutex<Vec<u8>>>>,
+    started: HashMap<String, Instant>,
+    out: W,
+}
+
+impl<P, W: Write> GroupedOutput<P, W> {
+    pub(crate) fn new(
+        inner: P,
+        buffers: HashMap<String, Arc<std::sync::Mutex<Vec<u8>>>>,
+        out: W,
+    ) -> Self {
+        GroupedOutput {
+            inner,
+            buffers,
+            started: HashMap::new(),
+            out,
+        }
+    }
+
+    fn print(&mut self, name: &str, passed: bool) -> std::io::Result<()> {
+        let took = self
+            .started
+            .remove(name)
+            .map(|started| Duration::from_millis(started.elapsed().as_millis() as u64))
+            .unwrap_or_default();
+        let output = match self.buffers.get(name) {
+            Some(buffer) => std::mem::take(&mut *buffer.lock().unwrap()),
+            None => Vec::new(),
+        };
+
+        // All at once, so nothing else printed can land in the middle.
+        let mut block = format!(
+            "=== {name} ({}, {}) ===\n",
+            if passed { "PASS" } else { "FAIL" },
+            humantime::format_duration(took)
+        )
+        .into_bytes();
+        block.extend_from_slice(&output);
+        if !output.is_empty() && !output.ends_with(b"\n") {
+            block.push(b'\n');
+        }
+        block.extend_from_slice(format!("=== end {name} ===\n").as_bytes());
+        self.out.write_all(&block)?;
+        self.out.flush()
+    }
+}
+
+impl<P: ProgressListener, W: Write> ProgressListener for GroupedOutput<P, W> {
+    fn on_start(&mut self, name: &str) {
+        self.started.insert(name.to_string(), Instant::now());
+        self.inner.on_start(name);
+    }
+
+    fn on_finish(&mut self, name: &str, passed: bool) {
+        if let Err(e) = self.print(name, passed) {
+            eprintln!("Failed to print the output of {name}: {e}");
+        }
+        self.inner.on_finish(name, passed);
+    }
+
+    fn on_retry(&mut self, name: &str, attempt: usize, attempts: usize) {
+        self.inner.on_retry(name, attempt, attempts);
+    }
+
+    fn on_error(&mut self, name: &str, error: &dyn Display) {
+        self.inner.on_error(name, error);
+    }
+
+    fn on_queued(&mut self, queued: usize) {
+        self.inner.on_queued(queued);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<std::ffi::OsString> {
+        let vars = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), std::ffi::OsString::from(v)))
+            .collect::<HashMap<_, _>>();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn json_progress_writes_an_event_per_line() {
+        let mut out = Vec::new();
+        let mut progress = JsonProgress::new(&mut out);
+
+        progress.on_start("test //a:rust_crate");
+        progress.on_retry("test //a:rust_crate", 2, 3);
+        progress.on_finish("test //a:rust_crate", true);
+
+        let events = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["event"], "start");
+        assert_eq!(events[0]["name"], "test //a:rust_crate");
+        assert!(events[0]["ts"].is_u64());
+        assert_eq!(events[1]["event"], "retry");
+        assert_eq!(events[1]["attempt"], 2);
+        assert_eq!(events[2]["event"], "finish");
+        assert_eq!(events[2]["passed"], true);
+    }
+
+    #[test]
+    fn github_progress_annotates_failures() {
+        let mut out = Vec::new();
+        let mut progress = GithubProgress::new(ContinuousIntegrationProgress::new(1), &mut out);
+
+        progress.on_start("test //a:rust_crate");
+        progress.on_error("test //a:rust_crate", &"\nerror: 50% broken\ndetails");
+        progress.on_finish("test //a:rust_crate", false);
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                "::group::test //a:rust_crate",
+                "",
+                "error: 50% broken",
+                "details",
+                "::endgroup::",
+                "::error title=//a%3Arust_crate::error: 50%25 broken",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn grouped_output_prints_each_task_in_one_block() {
+        let buffers = HashMap::from([
+            (String::from("test //a:x"), Arc::default()),
+            (String::from("test //b:x"), Arc::default()),
+        ]);
+        let mut progress = GroupedOutput::new(NullProgressListener, buffers.clone(), Vec::new());
+
+        progress.on_start("test //a:x");
+        progress.on_start("test //b:x");
+        for (name, line) in [
+            ("test //a:x", "a1\n"),
+            ("test //b:x", "b1\n"),
+            ("test //a:x", "a2"),
+        ] {
+            buffers[name]
+                .lock()
+                .unwrap()
+                .extend_from_slice(line.as_bytes());
+        }
+        progress.on_finish("test //b:x", false);
+        progress.on_finish("test //a:x", true);
+
+        let out = String::from_utf8(progress.out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("=== test //b:x (FAIL, "), "{out}");
+        assert_eq!(lines[1..3], ["b1", "=== end test //b:x ==="]);
+        assert!(lines[3].starts_with("=== test //a:x (PASS, "), "{out}");
+        assert_eq!(lines[4..], ["a1", "a2", "=== end test //a:x ==="]);
+    }
+
+    #[test]
+    fn status_file_has_the_current_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status.json");
+        let status =
+            || serde_json::from_slice::<serde_json::Value>(&std::fs::read(&path).unwrap()).unwrap();
+
+        let mut progress = StatusFileProgress::new(NullProgressListener, &path, 3);
+        assert_eq!(
+            status(),
+            serde_json::json!({ "running": [], "finished": 0, "total": 3 })
+        );
+
+        progress.on_start("test //a:rust_crate");
+        progress.on_start("test //b:go_mod");
+        progress.on_finish("test //a:rust_crate", true);
+        assert_eq!(
+            status(),
+            serde_json::json!({ "running": ["test //b:go_mod"], "finished": 1, "total": 3 })
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn detects_ci_from_any_ci_value() {
+        assert!(detect_ci_in(env(&[("CI", "true")])));
+        assert!(detect_ci_in(env(&[("CI", "1")])));
+        assert!(detect_ci_in(env(&[("CI", "TRUE")])));
+        assert!(!detect_ci_in(env(&[("CI", "")])));
+        assert!(!detect_ci_in(env(&[])));
+    }
+
+    #[test]
+    fn detects_ci_from_provider_variables() {
+        assert!(detect_ci_in(env(&[("GITHUB_ACTIONS", "true")])));
+        assert!(detect_ci_in(env(&[("BUILDKITE", "true")])));
+        assert!(detect_ci_in(env(&[(
+            "JENKINS_URL",
+            "https://ci.example.com"
+        )])));
+        assert!(!detect_ci_in(env(&[("HOME", "/root")])));
+    }
+}
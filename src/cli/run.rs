@@ -0,0 +1,625 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    path::*,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use structopt::*;
+
+#[cfg(feature = "otel")]
+use crate::otel;
+use crate::{junit, profile, state::*, timing, watch};
+use gentle::{coverage, multi_runner::*, target::*, targets};
+
+use super::{config::Config, exit_usage, plan::*, progress::*};
+
+#[derive(StructOpt)]
+pub enum ActionCommand {
+    Test(ActionArgs),
+    Build(ActionArgs),
+    Fmt(ActionArgs),
+    Doc(ActionArgs),
+    Coverage(ActionArgs),
+    Check(ActionArgs),
+}
+
+impl ActionCommand {
+    fn into_parts(self) -> (Action, ActionArgs) {
+        match self {
+            ActionCommand::Test(args) => (Action::Test, args),
+            ActionCommand::Build(args) => (Action::Build, args),
+            ActionCommand::Fmt(args) => (Action::Fmt, args),
+            ActionCommand::Doc(args) => (Action::Doc, args),
+            ActionCommand::Coverage(args) => (Action::Coverage, args),
+            ActionCommand::Check(args) => (Action::Check, args),
+        }
+    }
+}
+
+#[derive(StructOpt)]
+pub struct ActionArgs {
+    /// Additional actions to run in the same invocation, followed by matchers selecting which
+    /// targets to run, e.g. `gentle build test //backend/...`.
+    args: Vec<String>,
+
+    /// Skip targets matching this even if other matchers select them, e.g. `//legacy/...`. May
+    /// be repeated.
+    #[structopt(long, number_of_values = 1)]
+    pub(crate) exclude: Vec<TargetMatcher>,
+
+    /// Fail any task that runs longer than this, e.g. `10m`.
+    #[structopt(long, parse(try_from_str = humantime::parse_duration))]
+    timeout: Option<Duration>,
+
+    /// Start no more tasks once the whole run has taken this long, e.g. `30m`. Running tasks
+    /// still finish, and the rest are reported as not run.
+    #[structopt(long, parse(try_from_str = humantime::parse_duration))]
+    time_limit: Option<Duration>,
+
+    /// Keep running remaining tasks after a failure and report every failure at the end.
+    #[structopt(long)]
+    keep_going: bool,
+
+    /// With `--fail-fast=false`, a failure stops new tasks from starting but lets the running
+    /// ones finish, reporting their failures too.
+    #[structopt(long, default_value = "true", parse(try_from_str))]
+    fail_fast: bool,
+
+    /// Number of times to re-run a failed task before reporting it as failed.
+    #[structopt(long, default_value = "0")]
+    retries: usize,
+
+    /// Run every task this many times, in parallel as `--jobs` allows, and report how many of
+    /// them passed, to find flaky tests. Implies `--force` and `--keep-going`.
+    #[structopt(long, default_value = "1")]
+    repeat: usize,
+
+    /// Print the tasks that would run without running them.
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Write a JUnit XML report of every task to this path.
+    #[structopt(long)]
+    junit: Option<PathBuf>,
+
+    /// Write a JSON file of every task's duration and outcome to this path.
+    #[structopt(long)]
+    timing_report: Option<PathBuf>,
+
+    /// Write a timeline of when each task ran, one row per slot of `--jobs`, to this path in the
+    /// Chrome trace event format read by `chrome://tracing` and Perfetto.
+    #[structopt(long)]
+    profile: Option<PathBuf>,
+
+    /// Where the `coverage` action writes the lcov report merged from every target's.
+    #[structopt(long, default_value = "lcov.info")]
+    coverage_out: PathBuf,
+
+    /// How to report progress: `term`, `ci` for plain log lines, `github` for those plus GitHub
+    /// Actions annotations, `json` for one JSON event per line on stdout, or `null`. `auto` picks
+    /// `json` if GENTLE_JSON=1, `github` if GITHUB_ACTIONS=true, `ci` when running in CI, `term`
+    /// if stderr is a terminal, and otherwise `null`.
+    #[structopt(long, default_value = "auto")]
+    progress: Progress,
+
+    /// What to print of tasks' output: `errors` for only the output of failed tasks with their
+    /// errors, or `grouped` to also print each task's whole output in one block once it finishes,
+    /// so output of tasks running at once isn't interleaved.
+    #[structopt(long, default_value = "errors")]
+    output_style: OutputStyle,
+
+    /// Run tasks even if their inputs haven't changed since they last passed.
+    #[structopt(long)]
+    force: bool,
+
+    /// Also skip tasks that passed when they last ran but can't tell whether their inputs changed.
+    #[structopt(long)]
+    skip_passed: bool,
+
+    /// Whether to color task and progress output: `always`, `never`, or `auto` to color only
+    /// when stderr is a terminal and NO_COLOR isn't set.
+    #[structopt(long, default_value = "auto")]
+    color: Color,
+
+    /// Keep a JSON snapshot of the running tasks and how many have finished at this path,
+    /// replaced whenever a task starts or finishes.
+    #[structopt(long)]
+    status_file: Option<PathBuf>,
+
+    /// Only run targets with files that changed since this git ref, e.g. `origin/main`, and the
+    /// targets depending on them. Runs every target if git can't tell.
+    #[structopt(long)]
+    since: Option<String>,
+
+    /// After running, re-run the targets whose directories change until interrupted.
+    #[structopt(long)]
+    watch: bool,
+}
+
+impl ActionArgs {
+    /// Every file a run writes reports to.
+    fn outputs(&self) -> Vec<PathBuf> {
+        [
+            &self.junit,
+            &self.timing_report,
+            &self.profile,
+            &self.status_file,
+        ]
+        .into_iter()
+        .flatten()
+        .chain([&self.coverage_out])
+        .cloned()
+        .collect()
+    }
+
+    fn actions_and_matchers(
+        &self,
+        first: Action,
+    ) -> anyhow::Result<(Vec<Action>, Vec<TargetMatcher>)> {
+        let mut actions = vec![first];
+        let mut matchers = Vec::new();
+
+        for arg in &self.args {
+            if arg.starts_with("//") {
+                matchers.push(arg.parse()?);
+                continue;
+            }
+
+            let action = arg.parse()?;
+            if !actions.contains(&action) {
+                actions.push(action);
+            }
+        }
+
+        if matchers.is_empty() {
+            matchers.push("//...".parse()?);
+        }
+
+        Ok((actions, matchers))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    Test,
+    Build,
+    Fmt,
+    Doc,
+    Coverage,
+    Check,
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Test => write!(f, "test"),
+            Action::Build => write!(f, "build"),
+            Action::Fmt => write!(f, "fmt"),
+            Action::Doc => write!(f, "doc"),
+            Action::Coverage => write!(f, "coverage"),
+            Action::Check => write!(f, "check"),
+        }
+    }
+}
+
+impl std::str::FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "test" => Ok(Action::Test),
+            "build" => Ok(Action::Build),
+            "fmt" => Ok(Action::Fmt),
+            "doc" => Ok(Action::Doc),
+            "coverage" => Ok(Action::Coverage),
+            "check" => Ok(Action::Check),
+            _ => Err(anyhow::anyhow!("unknown action: {s:?}")),
+        }
+    }
+}
+
+/// `fingerprint` combined with the environment a task runs with, which affects it as much as its
+/// files do.
+fn with_env(fingerprint: &str, env: &BTreeMap<String, String>) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(fingerprint.as_bytes());
+    for (name, value) in env {
+        hasher.update(&[0]);
+        hasher.update(name.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(value.as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Runs every action on every target, skipping tasks that passed with the same inputs.
+fn run_tasks(
+    args: &ActionArgs,
+    actions: &[Action],
+    targets: &[Arc<dyn targets::Target>],
+    config: &Config,
+    jobs: Option<usize>,
+    load: Option<usize>,
+    log_dir: Option<&Path>,
+) -> anyhow::Result<()> {
+    let jobs = jobs.filter(|&jobs| jobs > 0);
+    let started = Instant::now();
+    let mut state = State::load(STATE_FILE)?;
+    let mut fingerprints = HashMap::new();
+    let mut tasks = Vec::new();
+    let mut skipped = 0;
+    for &action in actions {
+        for target in targets {
+            let name = format!("{action} {target}");
+            if args.repeat > 1 {
+                for run in 1..=args.repeat {
+                    tasks.push((format!("{name} #{run}"), action, Arc::clone(target)));
+                }
+                continue;
+            }
+
+            // Targets that can't be fingerprinted always run, unless trusted to still pass.
+            let env = config.env(&target.address(), |var| std::env::var(var).ok());
+            let fingerprint = target
+                .input_fingerprint()
+                .ok()
+                .flatten()
+                .map(|fingerprint| with_env(&fingerprint, &env));
+            let unchanged = match (state.passed.get(&name), &fingerprint) {
+                (Some(Some(passed)), Some(fingerprint)) => passed == fingerprint,
+                (Some(_), None) => args.skip_passed,
+                _ => false,
+            };
+            // A skipped target would be missing from the coverage report.
+            if unchanged && !args.force && action != Action::Coverage {
+                eprintln!("Skipping {name}, unchanged since it passed");
+                skipped += 1;
+                continue;
+            }
+            if let Some(fingerprint) = fingerprint {
+                fingerprints.insert(name.clone(), fingerprint);
+            }
+
+            tasks.push((name, action, Arc::clone(target)));
+        }
+    }
+
+    let color = args.color.enabled();
+    let progress_kind = args.progress.resolve();
+    let progress: Box<dyn ProgressListener> = match progress_kind {
+        Progress::Json => Box::new(JsonProgress::new(std::io::stdout())),
+        Progress::Ci => Box::new(ContinuousIntegrationProgress::new(tasks.len())),
+        Progress::Github => Box::new(GithubProgress::new(
+            ContinuousIntegrationProgress::new(tasks.len()),
+            std::io::stdout(),
+        )),
+        Progress::Term => Box::new(TermProgress::new(tasks.len(), color)),
+        Progress::Null | Progress::Auto => Box::new(NullProgressListener),
+    };
+    // Each task's output, printed in one block when it finishes.
+    let buffers = match args.output_style {
+        OutputStyle::Grouped => tasks
+            .iter()
+            .map(|(name, _, _)| (name.clone(), Arc::default()))
+            .collect(),
+        OutputStyle::Errors => HashMap::new(),
+    };
+    let progress: Box<dyn ProgressListener> = match args.output_style {
+        OutputStyle::Grouped => Box::new(GroupedOutput::new(
+            progress,
+            buffers.clone(),
+            std::io::stderr(),
+        )),
+        OutputStyle::Errors => progress,
+    };
+    let progress: Box<dyn ProgressListener> = match &args.status_file {
+        Some(path) => Box::new(StatusFileProgress::new(progress, path, tasks.len())),
+        None => progress,
+    };
+    #[cfg(feature = "otel")]
+    let exporter = otel::Exporter::from_env();
+    let mut runner = match load.filter(|&load| load > 0).or(jobs) {
+        Some(capacity) => ParRunner::with_parallel(capacity, progress),
+        None => {
+            eprintln!("Running up to {} tasks in parallel", num_cpus::get());
+            ParRunner::new(progress)
+        }
+    };
+    if let Some(timeout) = args.timeout {
+        runner = runner.with_timeout(timeout);
+    }
+    if let Some(limit) = args.time_limit {
+        runner = runner.with_deadline(started + limit);
+    }
+    if args.keep_going || args.repeat > 1 {
+        runner = runner.keep_going();
+    }
+    if !args.fail_fast {
+        runner = runner.finish_running();
+    }
+    runner = runner.with_retries(args.retries).expecting(tasks.len());
+    let ctx = targets::TaskContext {
+        timeout: args.timeout,
+        log: None,
+        buffer: None,
+        color,
+        jobs: Some(jobs_per_task(jobs, tasks.len(), num_cpus::get())),
+        env: BTreeMap::new(),
+    };
+    if let Some(dir) = log_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Each target's coverage report, merged once they've all run.
+    let coverage_dir = std::env::temp_dir().join(format!("gentle-coverage-{}", std::process::id()));
+    let mut coverage_reports = Vec::new();
+    if actions.contains(&Action::Coverage) {
+        std::fs::create_dir_all(&coverage_dir)?;
+    }
+
+    let dependencies = task_dependencies(&tasks);
+    let order = dependency_order(&dependencies).map_err(|cycle| {
+        let names = cycle.iter().map(|&i| tasks[i].0.as_str());
+        anyhow::anyhow!(
+            "dependency cycle: {}",
+            names.collect::<Vec<_>>().join(" -> ")
+        )
+    })?;
+
+    for index in order {
+        let (name, action, target) = tasks[index].clone();
+        let after = dependencies[index]
+            .iter()
+            .map(|&d| tasks[d].0.clone())
+            .collect::<Vec<_>>();
+        let mut ctx = ctx.clone();
+        ctx.env = config.env(&target.address(), |var| std::env::var(var).ok());
+        ctx.buffer = buffers.get(&name).cloned();
+        if let Some(dir) = log_dir {
+            let log = dir.join(log_file_name(&name));
+            let _ = std::fs::remove_file(&log);
+            ctx.log = Some(log);
+        }
+        let coverage = coverage_dir.join(Path::new(&log_file_name(&name)).with_extension("lcov"));
+        if action == Action::Coverage {
+            coverage_reports.push(coverage.clone());
+        }
+        let weight = target.weight() as usize;
+        let address = target.address().to_string();
+        let perform = move || match action {
+            Action::Test => target.perform_test(&ctx),
+            Action::Build => target.perform_build(&ctx),
+            Action::Fmt => target.perform_fmt_check(&ctx),
+            Action::Doc => target.perform_doc(&ctx),
+            Action::Coverage => target.perform_coverage(&ctx, &coverage),
+            Action::Check => target.perform_check(&ctx),
+        };
+        let scheduled = runner.run_labeled(
+            &name,
+            &action.to_string(),
+            &address,
+            weight,
+            &after,
+            perform,
+        );
+        if scheduled.is_err() {
+            break;
+        }
+    }
+
+    let summary = runner.into_wait();
+    // Repeats tell whether a task is flaky, they don't make the next run skip it.
+    if args.repeat <= 1 && state.record(&summary, &fingerprints) {
+        state.save(STATE_FILE)?;
+    }
+    if matches!(progress_kind, Progress::Ci | Progress::Github) {
+        print_runtime_report(&summary);
+    }
+    if let Some(path) = &args.junit {
+        junit::write_report(path, &summary)?;
+    }
+    if let Some(path) = &args.timing_report {
+        timing::write_report(path, &summary)?;
+    }
+    if let Some(path) = &args.profile {
+        profile::write_report(path, &summary)?;
+    }
+    #[cfg(feature = "otel")]
+    if let Some(exporter) = &exporter {
+        exporter.finish(&summary);
+    }
+    if actions.contains(&Action::Coverage) {
+        let written = coverage::write_report(&args.coverage_out, &coverage_reports);
+        let _ = std::fs::remove_dir_all(&coverage_dir);
+        written?;
+    }
+
+    let not_run = tasks.len() - summary.results.len();
+    let failed = summary.failures().count();
+    let passed = summary.results.len() - failed;
+    let mut line = format!("{passed} passed, {failed} failed, {skipped} skipped");
+    if not_run > 0 {
+        line += &format!(", {not_run} not run");
+    }
+    let took = Duration::from_millis(started.elapsed().as_millis() as u64);
+    eprintln!("{line} in {}", humantime::format_duration(took));
+    let out_of_time = args
+        .time_limit
+        .filter(|&limit| not_run > 0 && took >= limit);
+    if args.repeat > 1 {
+        for ((action, address), (passed, runs)) in repeat_counts(&summary) {
+            eprintln!("{action} {address}: {passed}/{runs} passed");
+        }
+    }
+
+    let mut failures = summary.into_failures();
+    if !failures.is_empty() {
+        if failures.len() == 1 {
+            return Err(task_error(failures.remove(0)));
+        }
+
+        let count = failures.len();
+        for failure in failures {
+            eprintln!("{:?}\n", task_error(failure));
+        }
+        anyhow::bail!("{count} tasks failed");
+    }
+    // Everything that ran passed, but not everything ran.
+    if let Some(limit) = out_of_time {
+        anyhow::bail!(
+            "reached the time limit of {} with {not_run} tasks not run",
+            humantime::format_duration(limit)
+        );
+    }
+
+    Ok(())
+}
+
+/// Each task's share of `cores`, so the tasks running at once don't oversubscribe them together.
+/// `--load` is a budget of weight rather than of tasks, so only `--jobs` says how many those are.
+fn jobs_per_task(jobs: Option<usize>, tasks: usize, cores: usize) -> usize {
+    let concurrent = jobs.unwrap_or(cores).min(tasks).max(1);
+    (cores / concurrent).max(1)
+}
+
+/// `test //foo/bar:baz` becomes `test-foo-bar-baz.log`.
+fn log_file_name(task: &str) -> String {
+    format!(
+        "{}.log",
+        task.replace("//", "").replace([' ', '/', ':'], "-")
+    )
+}
+
+fn task_error((name, err): Failure<anyhow::Error>) -> anyhow::Error {
+    match err {
+        TaskError::Failed(e) => e,
+        other => anyhow::anyhow!("{other}"),
+    }
+    .context(name)
+}
+
+/// Each repeated task's action and address, with how many of its runs passed out of how many ran.
+fn repeat_counts<E>(summary: &RunSummary<E>) -> BTreeMap<(&str, &str), (usize, usize)> {
+    let mut counts = BTreeMap::<_, (usize, usize)>::new();
+    for result in &summary.results {
+        let task = (result.action.as_str(), result.address.as_str());
+        let (passed, runs) = counts.entry(task).or_default();
+        *passed += usize::from(result.result.is_ok());
+        *runs += 1;
+    }
+    counts
+}
+
+fn print_runtime_report<E>(summary: &RunSummary<E>) {
+    eprintln!("Runtime report:");
+
+    for result in summary.by_duration() {
+        eprintln!(
+            "  {}: {}",
+            humantime::format_duration(result.duration),
+            result.name
+        );
+    }
+}
+
+/// Runs the actions of `command` on the targets it selects, and again on those that change if
+/// it's watching.
+pub(super) fn run_actions(
+    command: ActionCommand,
+    config: &Config,
+    discovery: &targets::Discovery,
+    jobs: Option<usize>,
+    load: Option<usize>,
+    log_dir: Option<&Path>,
+) -> anyhow::Result<()> {
+    let (first, args) = command.into_parts();
+    let (actions, matchers) = args
+        .actions_and_matchers(first)
+        .unwrap_or_else(|e| exit_usage(e));
+
+    let mut targets = select_targets(config, discovery, &matchers, &args.exclude)?
+        .into_iter()
+        .map(Arc::<dyn targets::Target>::from)
+        .collect::<Vec<_>>();
+    if let Some(since) = &args.since {
+        match changed_since(since) {
+            Ok(changed) => targets = touched(&targets, &changed),
+            Err(e) => eprintln!("Running every target, can't tell what changed: {e}"),
+        }
+    }
+
+    if args.dry_run {
+        for &action in &actions {
+            for target in &targets {
+                println!("{action} {target}");
+            }
+        }
+        return Ok(());
+    }
+
+    let result = run_tasks(&args, &actions, &targets, config, jobs, load, log_dir);
+    if !args.watch {
+        return result;
+    }
+    if let Err(e) = result {
+        eprintln!("Error: {e:?}");
+    }
+
+    let mut outputs = args.outputs();
+    outputs.extend(log_dir.map(Path::to_path_buf));
+    watch::watch(&targets, &outputs, |changed| {
+        let result = run_tasks(&args, &actions, changed, config, jobs, load, log_dir);
+        if let Err(e) = result {
+            eprintln!("Error: {e:?}");
+        }
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cores_are_split_between_concurrent_tasks() {
+        assert_eq!(jobs_per_task(None, 100, 8), 1);
+        assert_eq!(jobs_per_task(Some(2), 100, 8), 4);
+        // A task running alone gets every core.
+        assert_eq!(jobs_per_task(None, 1, 8), 8);
+        assert_eq!(jobs_per_task(Some(16), 0, 8), 8);
+    }
+
+    #[test]
+    fn repeats_are_counted_per_task() {
+        let result = |address: &str, run, passed| TaskResult {
+            name: format!("test {address} #{run}"),
+            action: String::from("test"),
+            address: address.to_string(),
+            started: Instant::now(),
+            duration: Duration::ZERO,
+            slot: None,
+            result: if passed {
+                Ok(())
+            } else {
+                Err(TaskError::Failed(()))
+            },
+        };
+        let summary = RunSummary {
+            results: vec![
+                result("//a:go_mod", 2, true),
+                result("//b:node #1", 1, true),
+                result("//a:go_mod", 1, false),
+                result("//a:go_mod", 3, true),
+            ],
+        };
+
+        assert_eq!(
+            repeat_counts(&summary),
+            BTreeMap::from([
+                (("test", "//a:go_mod"), (2, 3)),
+                (("test", "//b:node #1"), (1, 1))
+            ])
+        );
+    }
+}
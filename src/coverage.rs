@@ -0,0 +1,129 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// Line coverage in the lcov format most coverage tools read and write. Function and branch
+/// records are dropped, they can't be merged across tools.
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct Lcov {
+    /// Times each line of each file ran.
+    files: BTreeMap<PathBuf, BTreeMap<u32, u64>>,
+}
+
+impl Lcov {
+    pub fn parse(report: &str) -> anyhow::Result<Self> {
+        let mut lcov = Lcov::default();
+        let mut file = None;
+
+        for line in report.lines() {
+            let line = line.trim();
+            if let Some(path) = line.strip_prefix("SF:") {
+                file = Some(PathBuf::from(path));
+            } else if let Some(data) = line.strip_prefix("DA:") {
+                let file = file
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("line data outside a file: {line:?}"))?;
+                // Optionally followed by a checksum of the line.
+                let mut fields = data.split(',');
+                let (Some(number), Some(hits)) = (fields.next(), fields.next()) else {
+                    anyhow::bail!("malformed line data: {line:?}");
+                };
+                lcov.hit(file, number.parse()?, hits.parse()?);
+            } else if line == "end_of_record" {
+                file = None;
+            }
+        }
+
+        Ok(lcov)
+    }
+
+    /// Records `line` of `file` running `hits` more times.
+    pub fn hit(&mut self, file: &Path, line: u32, hits: u64) {
+        *self
+            .files
+            .entry(file.to_path_buf())
+            .or_default()
+            .entry(line)
+            .or_default() += hits;
+    }
+
+    pub fn merge(&mut self, other: Lcov) {
+        for (file, lines) in other.files {
+            for (line, hits) in lines {
+                self.hit(&file, line, hits);
+            }
+        }
+    }
+}
+
+impl Display for Lcov {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (file, lines) in &self.files {
+            writeln!(f, "SF:{}", file.display())?;
+            for (line, hits) in lines {
+                writeln!(f, "DA:{line},{hits}")?;
+            }
+            writeln!(f, "LF:{}", lines.len())?;
+            writeln!(f, "LH:{}", lines.values().filter(|&&hits| hits > 0).count())?;
+            writeln!(f, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
+
+/// Merges the lcov reports at `reports` into one at `out`. Missing reports are from targets
+/// that don't measure coverage, and are skipped.
+pub fn write_report(out: &Path, reports: &[PathBuf]) -> anyhow::Result<()> {
+    let mut merged = Lcov::default();
+    for report in reports {
+        let contents = match std::fs::read_to_string(report) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => anyhow::bail!("reading {}: {e}", report.display()),
+        };
+        let lcov = Lcov::parse(&contents)
+            .map_err(|e| anyhow::anyhow!("parsing {}: {e}", report.display()))?;
+        merged.merge(lcov);
+    }
+
+    std::fs::write(out, merged.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merging_adds_hits_of_the_same_lines() {
+        let mut lcov = Lcov::parse(
+            "TN:\nSF:/src/a.rs\nFN:1,main\nDA:1,1\nDA:2,0\nend_of_record\n\
+             SF:/src/b.rs\nDA:5,3,abc\nend_of_record\n",
+        )
+        .unwrap();
+        lcov.merge(Lcov::parse("SF:/src/a.rs\nDA:2,4\nDA:3,0\nend_of_record\n").unwrap());
+
+        assert_eq!(
+            lcov.to_string(),
+            "SF:/src/a.rs\nDA:1,1\nDA:2,4\nDA:3,0\nLF:3\nLH:2\nend_of_record\n\
+             SF:/src/b.rs\nDA:5,3\nLF:1\nLH:1\nend_of_record\n"
+        );
+    }
+
+    #[test]
+    fn missing_reports_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = dir.path().join("a.lcov");
+        std::fs::write(&report, "SF:a.go\nDA:1,1\nend_of_record\n").unwrap();
+
+        let out = dir.path().join("lcov.info");
+        write_report(&out, &[report, dir.path().join("missing.lcov")]).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(out).unwrap(),
+            "SF:a.go\nDA:1,1\nLF:1\nLH:1\nend_of_record\n"
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::{io::Write, path::Path, time::Duration};
+
+use gentle::multi_runner::{RunSummary, TaskError};
+
+pub fn write_report(path: &Path, summary: &RunSummary<anyhow::Error>) -> anyhow::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write(&mut file, summary)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn write(out: &mut impl Write, summary: &RunSummary<anyhow::Error>) -> std::io::Result<()> {
+    let total = summary.results.iter().map(|r| r.duration).sum::<Duration>();
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<testsuite name="gentle" tests="{}" failures="{}" time="{:.3}">"#,
+        summary.results.len(),
+        summary.failures().count(),
+        total.as_secs_f64(),
+    )?;
+
+    for result in &summary.results {
+        write!(
+            out,
+            r#"  <testcase classname="gentle" name="{}" time="{:.3}""#,
+            escape(&result.name),
+            result.duration.as_secs_f64(),
+        )?;
+
+        match &result.result {
+            Ok(()) => writeln!(out, " />")?,
+            Err(e) => {
+                let body = match e {
+                    TaskError::Failed(e) => format!("{e:#}"),
+                    other => other.to_string(),
+                };
+                let message = body.lines().next().unwrap_or_default();
+
+                writeln!(out, ">")?;
+                writeln!(
+                    out,
+                    r#"    <failure message="{}">{}</failure>"#,
+                    escape(message),
+                    escape(&body),
+                )?;
+                writeln!(out, "  </testcase>")?;
+            }
+        }
+    }
+
+    writeln!(out, "</testsuite>")
+}
+
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            // Control characters (like the ANSI escapes in colored output) aren't valid XML.
+            '\t' | '\n' | '\r' => result.push(c),
+            c if c.is_control() => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gentle::multi_runner::TaskResult;
+    use std::time::Instant;
+
+    #[test]
+    fn writes_passing_and_failing_cases() {
+        let summary = RunSummary {
+            results: vec![
+                TaskResult {
+                    name: String::from("test //foo:rust_crate"),
+                    action: String::from("test"),
+                    address: String::from("//foo:rust_crate"),
+                    started: Instant::now(),
+                    duration: Duration::from_millis(1500),
+                    slot: None,
+                    result: Ok(()),
+                },
+                TaskResult {
+                    name: String::from("test //bar:go_mod"),
+                    action: String::from("test"),
+                    address: String::from("//bar:go_mod"),
+                    started: Instant::now(),
+                    duration: Duration::from_millis(250),
+                    slot: None,
+                    result: Err(TaskError::Failed(anyhow::anyhow!(
+                        "\u{1b}[31mexpected <1> & got \"2\""
+                    ))),
+                },
+            ],
+        };
+
+        let mut out = Vec::new();
+        write(&mut out, &summary).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+
+        assert!(xml.contains(r#"<testsuite name="gentle" tests="2" failures="1" time="1.750">"#));
+        assert!(xml.contains(
+            r#"<testcase classname="gentle" name="test //foo:rust_crate" time="1.500" />"#
+        ));
+        assert!(
+            xml.contains(r#"<failure message="[31mexpected &lt;1&gt; &amp; got &quot;2&quot;">"#)
+        );
+    }
+}
@@ -0,0 +1,32 @@
+//! The build system behind the `gentle` binary, for driving its discovery and scheduling from
+//! other programs.
+//!
+//! ```no_run
+//! use gentle::{discover_targets, Discovery, NullProgressListener, ParRunner, TaskContext};
+//!
+//! let mut runner = ParRunner::new(NullProgressListener);
+//! for target in discover_targets(&Discovery::default())? {
+//!     let name = format!("test {target}");
+//!     let _ = runner.run(&name, move || target.perform_test(&TaskContext::default()));
+//! }
+//! for (name, error) in runner.into_wait().into_failures() {
+//!     eprintln!("{name}: {error}");
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod coverage;
+pub mod multi_runner;
+pub mod target;
+pub mod targets;
+
+pub use multi_runner::{
+    NullProgressListener, ParRunner, ProgressListener, RunSummary, Stopped, TaskError,
+};
+pub use target::{TargetAddress, TargetMatcher};
+pub use targets::{Discovery, Target, TaskContext};
+
+/// Every target under the working directory.
+pub fn discover_targets(discovery: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    targets::targets(discovery)
+}
@@ -1,101 +1,474 @@
 use std::{
+    any::Any,
     collections::HashMap,
-    sync::mpsc::{channel, Receiver, Sender},
-    thread::{spawn, JoinHandle},
+    fmt::Display,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::spawn,
+    time::{Duration, Instant},
 };
 
-pub type RunResult<E> = Result<(), (String, E)>;
+pub type Failure<E> = (String, TaskError<E>);
 
-pub struct ParRunner<E: Send + 'static, P: ProgressListener> {
-    max_threads: usize,
-    handles: HashMap<usize, JoinHandle<()>>,
-    names: HashMap<usize, String>,
+/// Returned by `ParRunner::run` once a task has failed and the runner is not keeping going.
+/// The failure itself is reported in the `RunSummary`, under the name of the task that failed,
+/// which needn't be the one being run. Also returned once the runner's deadline has passed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Stopped;
 
-    receiver: Receiver<(usize, Result<(), E>)>,
-    sender: Sender<(usize, Result<(), E>)>,
+pub type RunResult = Result<(), Stopped>;
+
+type Task<E> = Arc<dyn Fn() -> Result<(), E> + Send + Sync>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TaskError<E> {
+    Failed(E),
+    Panicked(String),
+    TimedOut(Duration),
+    /// The named task this one runs after failed, so this one never ran.
+    DependencyFailed(String),
+}
+
+impl<E: Display> Display for TaskError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskError::Failed(e) => write!(f, "{e}"),
+            TaskError::Panicked(message) => write!(f, "task panicked: {message}"),
+            TaskError::TimedOut(after) => {
+                write!(f, "timed out after {}", humantime::format_duration(*after))
+            }
+            TaskError::DependencyFailed(name) => write!(f, "dependency {name} failed"),
+        }
+    }
+}
+
+pub struct TaskResult<E> {
+    pub name: String,
+    /// What the task did, e.g. `test`, if it was run with `ParRunner::run_labeled`.
+    pub action: String,
+    /// The target the task did it to, if it was run with `ParRunner::run_labeled`.
+    pub address: String,
+    /// When the first attempt started.
+    pub started: Instant,
+    pub duration: Duration,
+    /// The runner slot it ran in, or `None` if it never ran.
+    pub slot: Option<usize>,
+    pub result: Result<(), TaskError<E>>,
+}
+
+pub struct RunSummary<E> {
+    pub results: Vec<TaskResult<E>>,
+}
+
+impl<E> RunSummary<E> {
+    /// Results ordered from fastest to slowest.
+    pub fn by_duration(&self) -> Vec<&TaskResult<E>> {
+        let mut results = self.results.iter().collect::<Vec<_>>();
+        results.sort_by_key(|r| r.duration);
+        results
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &TaskResult<E>> {
+        self.results.iter().filter(|r| r.result.is_err())
+    }
+
+    pub fn into_failures(self) -> Vec<Failure<E>> {
+        self.results
+            .into_iter()
+            .filter_map(|r| r.result.err().map(|e| (r.name, e)))
+            .collect()
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("Box<dyn Any>")
+    }
+}
+
+pub struct ParRunner<E: Display + Send + 'static, P: ProgressListener> {
+    /// Most total weight of tasks running at once.
+    capacity: usize,
+    timeout: Option<Duration>,
+    /// No task starts after this.
+    deadline: Option<Instant>,
+    keep_going: bool,
+    finish_running: bool,
+    retries: usize,
+    /// Tasks the caller said it would run, including those not submitted yet.
+    expected: usize,
+    started: usize,
+    /// The queue depth listeners last heard, to only tell them about changes.
+    reported_queued: Option<usize>,
+    stopped: bool,
+    results: Vec<TaskResult<E>>,
+    running: HashMap<usize, Running<E>>,
+    pending: Vec<Pending<E>>,
+    /// The action and address of tasks run with `run_labeled`, by name.
+    labels: HashMap<String, (String, String)>,
+    next_task: u64,
+
+    receiver: Receiver<Finished<E>>,
+    sender: Sender<Finished<E>>,
 
     progress: P,
 }
 
+struct Running<E> {
+    task: u64,
+    name: String,
+    started: Instant,
+    first_started: Instant,
+    attempt: usize,
+    weight: usize,
+    f: Task<E>,
+}
+
+struct Pending<E> {
+    name: String,
+    weight: usize,
+    after: Vec<String>,
+    f: Task<E>,
+}
+
+struct Finished<E> {
+    id: usize,
+    task: u64,
+    result: Result<(), TaskError<E>>,
+}
+
 pub trait ProgressListener {
     fn on_start(&mut self, name: &str);
-    fn on_finish(&mut self, name: &str);
+    /// A task is done for good, `passed` is false if it failed.
+    fn on_finish(&mut self, name: &str, passed: bool);
+
+    /// A failed task is being run again, `attempt` is 1-based and at most `attempts`.
+    fn on_retry(&mut self, _name: &str, _attempt: usize, _attempts: usize) {}
+
+    /// A task has failed for good, called just before its `on_finish` with the error.
+    fn on_error(&mut self, _name: &str, _error: &dyn Display) {}
+
+    /// How many tasks are waiting to start, called whenever that changes.
+    fn on_queued(&mut self, _queued: usize) {}
 }
 
-impl<E: Send + 'static, P: ProgressListener> ParRunner<E, P> {
-    #[allow(dead_code)]
+impl<E: Display + Send + 'static, P: ProgressListener> ParRunner<E, P> {
     pub fn new(p: P) -> Self {
-        let parallel = num_cpus::get();
-        eprintln!("Running up to {parallel} tasks in parallel");
-        Self::with_parallel(parallel, p)
+        Self::with_parallel(num_cpus::get(), p)
     }
 
-    #[allow(dead_code)]
-    pub fn with_parallel(max_threads: usize, progress: P) -> Self {
+    pub fn with_parallel(capacity: usize, progress: P) -> Self {
         let (sender, receiver) = channel();
 
         ParRunner {
-            max_threads,
-            handles: Default::default(),
-            names: Default::default(),
+            capacity,
+            timeout: None,
+            deadline: None,
+            keep_going: false,
+            finish_running: false,
+            retries: 0,
+            expected: 0,
+            started: 0,
+            reported_queued: None,
+            stopped: false,
+            results: Vec::new(),
+            running: Default::default(),
+            pending: Vec::new(),
+            labels: HashMap::new(),
+            next_task: 0,
             sender,
             receiver,
             progress,
         }
     }
 
+    /// Start no tasks after `deadline`, leaving them out of the summary. Running tasks still
+    /// finish.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    fn past_deadline(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Fail any task that runs longer than `timeout`, freeing its slot for the next task.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Keep scheduling tasks after a failure, reporting every failure from `into_wait`.
+    pub fn keep_going(mut self) -> Self {
+        self.keep_going = true;
+        self
+    }
+
+    /// After a failure, start no new tasks but let the running ones finish, reporting their
+    /// results from `into_wait` too.
+    pub fn finish_running(mut self) -> Self {
+        self.finish_running = true;
+        self
+    }
+
+    /// Run a failed task up to `retries` more times before reporting it as failed.
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How many tasks will be run in total, so listeners hear how many are queued before they're
+    /// all submitted.
+    pub fn expecting(mut self, tasks: usize) -> Self {
+        self.expected = tasks;
+        self.report_queued();
+        self
+    }
+
+    /// Tasks not started yet, whether submitted or only expected.
+    fn queued(&self) -> usize {
+        self.expected
+            .saturating_sub(self.started)
+            .max(self.pending.len())
+    }
+
+    fn report_queued(&mut self) {
+        let queued = self.queued();
+        if self.reported_queued != Some(queued) {
+            self.reported_queued = Some(queued);
+            self.progress.on_queued(queued);
+        }
+    }
+
     pub fn run(
         &mut self,
         name: &str,
-        f: impl FnOnce() -> Result<(), E> + Send + 'static,
-    ) -> RunResult<E> {
-        self.check_finished()?;
+        f: impl Fn() -> Result<(), E> + Send + Sync + 'static,
+    ) -> RunResult {
+        self.run_after(name, &[], f)
+    }
 
-        if self.handles.len() >= self.max_threads {
-            self.wait_receive_one()?;
+    /// Like `run`, but the task only starts once every task named in `after` has passed. If one
+    /// of them fails, this task fails with `TaskError::DependencyFailed` without running.
+    ///
+    /// Tasks in `after` must be run on this runner too, or this task never starts.
+    pub fn run_after(
+        &mut self,
+        name: &str,
+        after: &[String],
+        f: impl Fn() -> Result<(), E> + Send + Sync + 'static,
+    ) -> RunResult {
+        self.run_weighted(name, 1, after, f)
+    }
+
+    /// Like `run_after`, but the task takes up `weight` of the runner's parallelism instead of
+    /// one. A task heavier than the whole runner runs alone.
+    pub fn run_weighted(
+        &mut self,
+        name: &str,
+        weight: usize,
+        after: &[String],
+        f: impl Fn() -> Result<(), E> + Send + Sync + 'static,
+    ) -> RunResult {
+        if self.stopped || self.past_deadline() {
+            return Err(Stopped);
         }
 
-        let id = (0..self.max_threads)
-            .find(|n| !self.handles.contains_key(n))
-            .unwrap();
+        self.pending.push(Pending {
+            name: name.to_string(),
+            weight: weight.clamp(1, self.capacity.max(1)),
+            after: after.to_vec(),
+            f: Arc::new(f),
+        });
+        self.report_queued();
+        self.start_ready()
+    }
 
-        let sender = self.sender.clone();
-        self.handles
-            .insert(id, spawn(move || sender.send((id, f())).unwrap()));
+    /// Like `run_weighted`, but the task's result records the `action` it performs on the
+    /// target at `address`, for reports to break results down by.
+    pub fn run_labeled(
+        &mut self,
+        name: &str,
+        action: &str,
+        address: &str,
+        weight: usize,
+        after: &[String],
+        f: impl Fn() -> Result<(), E> + Send + Sync + 'static,
+    ) -> RunResult {
+        self.labels
+            .insert(name.to_string(), (action.to_string(), address.to_string()));
+        self.run_weighted(name, weight, after, f)
+    }
 
-        self.progress.on_start(&name);
+    fn load(&self) -> usize {
+        self.running.values().map(|r| r.weight).sum()
+    }
 
-        self.names.insert(id, name.to_string());
+    /// Start pending tasks in order as their dependencies finish, waiting for free slots.
+    fn start_ready(&mut self) -> RunResult {
+        self.check_finished()?;
 
-        Ok(())
+        loop {
+            if self.past_deadline() {
+                return Ok(());
+            }
+            let ready = self
+                .pending
+                .iter()
+                .enumerate()
+                .find_map(|(index, pending)| {
+                    let dependencies = pending
+                        .after
+                        .iter()
+                        .map(|name| self.results.iter().find(|r| &r.name == name))
+                        .collect::<Option<Vec<_>>>()?;
+                    let failed = dependencies.into_iter().find(|r| r.result.is_err());
+                    Some((index, failed.map(|r| r.name.clone())))
+                });
+            let Some((index, failed_dependency)) = ready else {
+                return Ok(());
+            };
+
+            if let Some(dependency) = failed_dependency {
+                let pending = self.pending.remove(index);
+                let error = TaskError::DependencyFailed(dependency);
+                self.started += 1;
+                self.report_queued();
+                self.progress.on_start(&pending.name);
+                self.progress.on_error(&pending.name, &error);
+                self.progress.on_finish(&pending.name, false);
+                self.record(pending.name, Instant::now(), None, Err(error))?;
+                continue;
+            }
+
+            let weight = self.pending[index].weight;
+            while self.load() + weight > self.capacity && !self.running.is_empty() {
+                self.wait_receive_one()?;
+            }
+            if self.past_deadline() {
+                return Ok(());
+            }
+
+            // Every running task weighs at least one, so there's a free id below the capacity.
+            let id = (0..self.capacity.max(1))
+                .find(|n| !self.running.contains_key(n))
+                .unwrap();
+
+            let pending = self.pending.remove(index);
+            self.started += 1;
+            self.report_queued();
+            self.progress.on_start(&pending.name);
+            self.spawn(id, pending.name, pending.f, 1, weight, Instant::now());
+        }
+    }
+
+    fn spawn(
+        &mut self,
+        id: usize,
+        name: String,
+        f: Task<E>,
+        attempt: usize,
+        weight: usize,
+        first_started: Instant,
+    ) {
+        let task = self.next_task;
+        self.next_task += 1;
+
+        let sender = self.sender.clone();
+        let run = Arc::clone(&f);
+        spawn(move || {
+            // Without catching the panic nothing is sent and the receiver waits forever.
+            let result = match catch_unwind(AssertUnwindSafe(|| run())) {
+                Ok(r) => r.map_err(TaskError::Failed),
+                Err(panic) => Err(TaskError::Panicked(panic_message(panic))),
+            };
+            // The runner may have given up on this task and been dropped already.
+            let _ = sender.send(Finished { id, task, result });
+        });
+
+        self.running.insert(
+            id,
+            Running {
+                task,
+                name,
+                started: Instant::now(),
+                first_started,
+                attempt,
+                weight,
+                f,
+            },
+        );
     }
 
-    fn check_finished(&mut self) -> RunResult<E> {
-        while let Ok((id, r)) = self.receiver.try_recv() {
-            let name = self.on_finished(id);
-            if let Err(e) = r {
-                return Err((name, e));
+    fn check_finished(&mut self) -> RunResult {
+        while let Ok(finished) = self.receiver.try_recv() {
+            self.on_received(finished)?;
+        }
+
+        while let Some((id, deadline)) = self.next_deadline() {
+            if deadline > Instant::now() {
+                break;
             }
+            self.on_timed_out(id)?;
         }
 
         Ok(())
     }
 
-    fn wait_receive_one(&mut self) -> RunResult<E> {
-        let (id, r) = self.receiver.recv().unwrap();
-        let name = self.on_finished(id);
-        r.map_err(|e| (name, e))
+    fn wait_receive_one(&mut self) -> RunResult {
+        loop {
+            let finished = match self.next_deadline() {
+                None => self.receiver.recv().unwrap(),
+                Some((id, deadline)) => {
+                    let wait = deadline.saturating_duration_since(Instant::now());
+                    match self.receiver.recv_timeout(wait) {
+                        Ok(finished) => finished,
+                        Err(RecvTimeoutError::Timeout) => return self.on_timed_out(id),
+                        Err(RecvTimeoutError::Disconnected) => {
+                            unreachable!("runner holds a sender")
+                        }
+                    }
+                }
+            };
+
+            if self.is_current(&finished) {
+                return self.on_received(finished);
+            }
+        }
     }
 
-    pub fn into_wait(mut self) -> RunResult<E> {
-        let r = self.wait_receive_all();
-        self.handles.clear();
-        r
+    pub fn into_wait(mut self) -> RunSummary<E> {
+        if !self.stopped {
+            let _ = self.wait_receive_all();
+        }
+        if self.finish_running {
+            while !self.running.is_empty() {
+                // Already stopped, so this only records the result.
+                let _ = self.wait_receive_one();
+            }
+        }
+        self.running.clear();
+        // Otherwise dropping the runner would start them.
+        self.pending.clear();
+
+        RunSummary {
+            results: std::mem::take(&mut self.results),
+        }
     }
 
-    fn wait_receive_all(&mut self) -> RunResult<E> {
+    fn wait_receive_all(&mut self) -> RunResult {
         loop {
-            if self.handles.len() == 0 {
+            self.start_ready()?;
+            if self.running.is_empty() {
                 return Ok(());
             }
 
@@ -103,15 +476,93 @@ impl<E: Send + 'static, P: ProgressListener> ParRunner<E, P> {
         }
     }
 
-    fn on_finished(&mut self, id: usize) -> String {
-        self.handles.remove(&id);
-        let name = self.names.remove(&id).expect("on_finished with missing id");
-        self.progress.on_finish(&name);
-        name
+    fn complete(&mut self, id: usize, result: Result<(), TaskError<E>>) -> RunResult {
+        let running = &self.running[&id];
+        if result.is_err() && running.attempt <= self.retries {
+            let (name, f, attempt, weight, first_started) = (
+                running.name.clone(),
+                Arc::clone(&running.f),
+                running.attempt + 1,
+                running.weight,
+                running.first_started,
+            );
+            self.progress.on_retry(&name, attempt, self.retries + 1);
+            self.spawn(id, name, f, attempt, weight, first_started);
+            return Ok(());
+        }
+
+        if let Err(error) = &result {
+            let name = &self.running[&id].name;
+            self.progress.on_error(name, error);
+        }
+        let running = self.on_finished(id, result.is_ok());
+        self.record(running.name, running.first_started, Some(id), result)
+    }
+
+    fn record(
+        &mut self,
+        name: String,
+        started: Instant,
+        slot: Option<usize>,
+        result: Result<(), TaskError<E>>,
+    ) -> RunResult {
+        let failed = result.is_err();
+        let (action, address) = self.labels.remove(&name).unwrap_or_default();
+        self.results.push(TaskResult {
+            name,
+            action,
+            address,
+            started,
+            duration: started.elapsed(),
+            slot,
+            result,
+        });
+
+        if failed && !self.keep_going {
+            self.stopped = true;
+            return Err(Stopped);
+        }
+        Ok(())
+    }
+
+    fn is_current(&self, finished: &Finished<E>) -> bool {
+        self.running
+            .get(&finished.id)
+            .is_some_and(|r| r.task == finished.task)
+    }
+
+    fn next_deadline(&self) -> Option<(usize, Instant)> {
+        let timeout = self.timeout?;
+        self.running
+            .iter()
+            .map(|(id, r)| (*id, r.started + timeout))
+            .min_by_key(|(_, deadline)| *deadline)
+    }
+
+    fn on_received(&mut self, finished: Finished<E>) -> RunResult {
+        if !self.is_current(&finished) {
+            return Ok(());
+        }
+
+        self.complete(finished.id, finished.result)
+    }
+
+    fn on_timed_out(&mut self, id: usize) -> RunResult {
+        let timeout = self.timeout.expect("timed out without a timeout");
+        self.complete(id, Err(TaskError::TimedOut(timeout)))
+    }
+
+    fn on_finished(&mut self, id: usize, passed: bool) -> Running<E> {
+        let running = self
+            .running
+            .remove(&id)
+            .expect("on_finished with missing id");
+        self.progress.on_finish(&running.name, passed);
+        running
     }
 }
 
-impl<E: Send + 'static, P: ProgressListener> Drop for ParRunner<E, P> {
+impl<E: Display + Send + 'static, P: ProgressListener> Drop for ParRunner<E, P> {
     fn drop(&mut self) {
         let _ = self.wait_receive_all();
     }
@@ -121,7 +572,7 @@ pub struct NullProgressListener;
 
 impl ProgressListener for NullProgressListener {
     fn on_start(&mut self, _: &str) {}
-    fn on_finish(&mut self, _: &str) {}
+    fn on_finish(&mut self, _: &str, _: bool) {}
 }
 
 impl<P> ProgressListener for P
@@ -133,8 +584,20 @@ where
         (**self).on_start(name)
     }
 
-    fn on_finish(&mut self, name: &str) {
-        (**self).on_finish(name)
+    fn on_finish(&mut self, name: &str, passed: bool) {
+        (**self).on_finish(name, passed)
+    }
+
+    fn on_retry(&mut self, name: &str, attempt: usize, attempts: usize) {
+        (**self).on_retry(name, attempt, attempts)
+    }
+
+    fn on_error(&mut self, name: &str, error: &dyn Display) {
+        (**self).on_error(name, error)
+    }
+
+    fn on_queued(&mut self, queued: usize) {
+        (**self).on_queued(queued)
     }
 }
 
@@ -143,17 +606,20 @@ mod tests {
     use super::*;
 
     use std::{
-        sync::{Arc, Mutex},
+        collections::HashSet,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
         thread::sleep,
-        time::Duration,
     };
 
     fn run_delayed(
-        par_runner: &mut ParRunner<(), NullProgressListener>,
+        par_runner: &mut ParRunner<&'static str, NullProgressListener>,
         finished: &Arc<Mutex<Vec<usize>>>,
         delay: u64,
         id: usize,
-    ) -> RunResult<()> {
+    ) -> RunResult {
         let clone = Arc::clone(finished);
         par_runner.run(&format!("task-{id}"), move || {
             sleep(Duration::from_millis(delay));
@@ -209,13 +675,14 @@ mod tests {
         par_runner
             .run("fails", || {
                 sleep(Duration::from_millis(10));
-                Err(())
+                Err("failed")
             })
             .unwrap();
 
+        assert_eq!(par_runner.run("ok", || Ok(())), Err(Stopped));
         assert_eq!(
-            par_runner.run("ok", || Ok(())),
-            Err((String::from("fails"), ()))
+            par_runner.into_wait().into_failures(),
+            vec![(String::from("fails"), TaskError::Failed("failed"))]
         );
     }
 
@@ -223,7 +690,7 @@ mod tests {
     fn failed_task_returns_err_at_next_opportunity() {
         let mut par_runner = ParRunner::with_parallel(2, NullProgressListener);
 
-        par_runner.run("fails", || Err(())).unwrap();
+        par_runner.run("fails", || Err("failed")).unwrap();
         sleep(Duration::from_millis(1));
 
         assert_eq!(
@@ -231,7 +698,11 @@ mod tests {
                 sleep(Duration::from_millis(10));
                 Ok(())
             }),
-            Err((String::from("fails"), ()))
+            Err(Stopped)
+        );
+        assert_eq!(
+            par_runner.into_wait().into_failures(),
+            vec![(String::from("fails"), TaskError::Failed("failed"))]
         );
     }
 
@@ -257,9 +728,489 @@ mod tests {
         let finished = Arc::new(Mutex::new(Vec::new()));
         run_delayed(&mut par_runner, &finished, 9, 0).unwrap();
 
-        par_runner.run("fails", || Err(())).unwrap();
+        par_runner.run("fails", || Err("failed")).unwrap();
+
+        assert_eq!(
+            par_runner.into_wait().into_failures(),
+            vec![(String::from("fails"), TaskError::Failed("failed"))]
+        );
+        assert_eq!(*finished.lock().unwrap(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn finish_running_waits_for_running_tasks_but_starts_no_more() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener).finish_running();
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        run_delayed(&mut par_runner, &finished, 20, 0).unwrap();
+        par_runner.run("fails", || Err("failed")).unwrap();
+        sleep(Duration::from_millis(5));
+        assert_eq!(run_delayed(&mut par_runner, &finished, 0, 1), Err(Stopped));
+
+        let summary = par_runner.into_wait();
+        assert_eq!(*finished.lock().unwrap(), vec![0]);
+        assert_eq!(summary.results.len(), 2);
+        assert_eq!(
+            summary.into_failures(),
+            vec![(String::from("fails"), TaskError::Failed("failed"))]
+        );
+    }
+
+    #[test]
+    fn no_tasks_start_after_the_deadline() {
+        let mut par_runner = ParRunner::with_parallel(1, NullProgressListener)
+            .with_deadline(Instant::now() + Duration::from_millis(20));
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        run_delayed(&mut par_runner, &finished, 40, 0).unwrap();
+        // Waits for the first task to free its slot, by which time it's too late to start.
+        run_delayed(&mut par_runner, &finished, 0, 1).unwrap();
+        assert_eq!(run_delayed(&mut par_runner, &finished, 0, 2), Err(Stopped));
+
+        let summary = par_runner.into_wait();
+        assert_eq!(*finished.lock().unwrap(), vec![0]);
+        assert_eq!(summary.results.len(), 1);
+        assert_eq!(summary.into_failures(), vec![]);
+    }
 
-        assert_eq!(par_runner.into_wait(), Err((String::from("fails"), ())));
-        assert_eq!(*finished.lock().unwrap(), vec![]);
+    #[test]
+    fn panicking_task_returns_err() {
+        let mut par_runner = ParRunner::<&str, _>::with_parallel(1, NullProgressListener);
+
+        par_runner.run("panics", || panic!("oh no")).unwrap();
+
+        assert_eq!(
+            par_runner.into_wait().into_failures(),
+            vec![(
+                String::from("panics"),
+                TaskError::Panicked(String::from("oh no"))
+            )]
+        );
+    }
+
+    #[test]
+    fn panicking_task_with_formatted_message() {
+        let mut par_runner = ParRunner::<&str, _>::with_parallel(1, NullProgressListener);
+
+        let code = 42;
+        par_runner
+            .run("panics", move || panic!("code {code}"))
+            .unwrap();
+
+        assert_eq!(
+            par_runner.into_wait().into_failures(),
+            vec![(
+                String::from("panics"),
+                TaskError::Panicked(String::from("code 42"))
+            )]
+        );
+    }
+
+    #[test]
+    fn timed_out_task_returns_err() {
+        let mut par_runner = ParRunner::with_parallel(1, NullProgressListener)
+            .with_timeout(Duration::from_millis(10));
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        run_delayed(&mut par_runner, &finished, 1000, 0).unwrap();
+
+        assert_eq!(
+            par_runner.into_wait().into_failures(),
+            vec![(
+                String::from("task-0"),
+                TaskError::TimedOut(Duration::from_millis(10))
+            )]
+        );
+    }
+
+    #[test]
+    fn timed_out_task_frees_its_slot() {
+        let mut par_runner = ParRunner::with_parallel(1, NullProgressListener)
+            .with_timeout(Duration::from_millis(10))
+            .keep_going();
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        run_delayed(&mut par_runner, &finished, 1000, 0).unwrap();
+        run_delayed(&mut par_runner, &finished, 1, 1).unwrap();
+
+        assert_eq!(
+            par_runner.into_wait().into_failures(),
+            vec![(
+                String::from("task-0"),
+                TaskError::TimedOut(Duration::from_millis(10))
+            )]
+        );
+        assert_eq!(*finished.lock().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn fast_task_is_not_timed_out() {
+        let mut par_runner = ParRunner::with_parallel(1, NullProgressListener)
+            .with_timeout(Duration::from_millis(100));
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        run_delayed(&mut par_runner, &finished, 1, 0).unwrap();
+
+        assert_eq!(par_runner.into_wait().into_failures(), vec![]);
+        assert_eq!(*finished.lock().unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn keep_going_runs_tasks_after_failure() {
+        let mut par_runner = ParRunner::with_parallel(1, NullProgressListener).keep_going();
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        par_runner.run("fails", || Err("failed")).unwrap();
+        run_delayed(&mut par_runner, &finished, 1, 0).unwrap();
+        run_delayed(&mut par_runner, &finished, 1, 1).unwrap();
+
+        assert_eq!(
+            par_runner.into_wait().into_failures(),
+            vec![(String::from("fails"), TaskError::Failed("failed"))]
+        );
+        assert_eq!(*finished.lock().unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn failure_is_reported_under_the_task_that_failed() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener);
+
+        par_runner.run("fails", || Err("failed")).unwrap();
+        sleep(Duration::from_millis(20));
+        // Scheduling notices the earlier failure, which must not be blamed on this task.
+        assert_eq!(par_runner.run("enqueued", || Ok(())), Err(Stopped));
+
+        let summary = par_runner.into_wait();
+        assert!(summary.results.iter().all(|r| r.name != "enqueued"));
+        assert_eq!(
+            summary.into_failures(),
+            vec![(String::from("fails"), TaskError::Failed("failed"))]
+        );
+    }
+
+    #[test]
+    fn keep_going_collects_all_failures() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener).keep_going();
+
+        par_runner.run("fails-0", || Err(0)).unwrap();
+        par_runner.run("fails-1", || Err(1)).unwrap();
+        par_runner.run("ok", || Ok(())).unwrap();
+        par_runner.run("fails-2", || Err(2)).unwrap();
+
+        let mut failures = par_runner.into_wait().into_failures();
+        failures.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            failures,
+            vec![
+                (String::from("fails-0"), TaskError::Failed(0)),
+                (String::from("fails-1"), TaskError::Failed(1)),
+                (String::from("fails-2"), TaskError::Failed(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_going_all_succeed() {
+        let mut par_runner =
+            ParRunner::<&str, _>::with_parallel(2, NullProgressListener).keep_going();
+
+        par_runner.run("ok-0", || Ok(())).unwrap();
+        par_runner.run("ok-1", || Ok(())).unwrap();
+
+        assert_eq!(par_runner.into_wait().into_failures(), vec![]);
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        retries: Vec<(String, usize, usize)>,
+        errors: Vec<(String, String)>,
+        queued: Vec<usize>,
+    }
+
+    impl ProgressListener for Recorder {
+        fn on_start(&mut self, _: &str) {}
+        fn on_finish(&mut self, _: &str, _: bool) {}
+
+        fn on_retry(&mut self, name: &str, attempt: usize, attempts: usize) {
+            self.retries.push((name.to_string(), attempt, attempts));
+        }
+
+        fn on_error(&mut self, name: &str, error: &dyn Display) {
+            self.errors.push((name.to_string(), error.to_string()));
+        }
+
+        fn on_queued(&mut self, queued: usize) {
+            self.queued.push(queued);
+        }
+    }
+
+    fn flaky(failures: usize) -> impl Fn() -> Result<(), usize> + Send + Sync {
+        let calls = AtomicUsize::new(0);
+        move || {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            if call < failures {
+                Err(call)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn reports_queue_depth_of_expected_tasks() {
+        let mut recorder = Recorder::default();
+        let mut par_runner = ParRunner::with_parallel(1, &mut recorder).expecting(3);
+
+        for name in ["a", "b", "c"] {
+            par_runner.run(name, || Ok::<_, usize>(())).unwrap();
+        }
+
+        assert_eq!(par_runner.into_wait().into_failures(), vec![]);
+        assert_eq!(recorder.queued, vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn retries_flaky_task_until_it_passes() {
+        let mut recorder = Recorder::default();
+        let mut par_runner = ParRunner::with_parallel(1, &mut recorder).with_retries(2);
+
+        par_runner.run("flaky", flaky(2)).unwrap();
+
+        assert_eq!(par_runner.into_wait().into_failures(), vec![]);
+        assert_eq!(
+            recorder.retries,
+            vec![(String::from("flaky"), 2, 3), (String::from("flaky"), 3, 3)]
+        );
+    }
+
+    #[test]
+    fn reports_error_once_retries_are_exhausted() {
+        let mut recorder = Recorder::default();
+        let mut par_runner = ParRunner::with_parallel(1, &mut recorder)
+            .with_retries(1)
+            .keep_going();
+
+        par_runner.run("flaky", flaky(2)).unwrap();
+        par_runner.run("ok", || Ok(())).unwrap();
+        drop(par_runner);
+
+        assert_eq!(
+            recorder.errors,
+            vec![(String::from("flaky"), String::from("1"))]
+        );
+    }
+
+    #[test]
+    fn gives_up_after_retries_are_exhausted() {
+        let mut par_runner = ParRunner::with_parallel(1, NullProgressListener).with_retries(2);
+
+        par_runner.run("flaky", flaky(3)).unwrap();
+
+        assert_eq!(
+            par_runner.into_wait().into_failures(),
+            vec![(String::from("flaky"), TaskError::Failed(2))]
+        );
+    }
+
+    #[test]
+    fn retrying_task_keeps_its_slot() {
+        let mut par_runner = ParRunner::with_parallel(1, NullProgressListener).with_retries(1);
+
+        par_runner
+            .run("flaky", {
+                let flaky = flaky(1);
+                move || {
+                    sleep(Duration::from_millis(5));
+                    flaky()
+                }
+            })
+            .unwrap();
+        par_runner.run("ok", || Ok(())).unwrap();
+
+        assert_eq!(par_runner.into_wait().into_failures(), vec![]);
+    }
+
+    #[test]
+    fn summary_records_every_task() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener).keep_going();
+
+        par_runner
+            .run("slow", || {
+                sleep(Duration::from_millis(20));
+                Ok(())
+            })
+            .unwrap();
+        par_runner.run("fails", || Err("failed")).unwrap();
+
+        let mut results = par_runner.into_wait().results;
+        results.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].name, "fails");
+        assert_eq!(results[0].result, Err(TaskError::Failed("failed")));
+        assert_eq!(results[1].name, "slow");
+        assert_eq!(results[1].result, Ok(()));
+        assert!(results[1].duration >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn summary_duration_includes_retries() {
+        let mut par_runner = ParRunner::with_parallel(1, NullProgressListener).with_retries(1);
+
+        let flaky = flaky(1);
+        par_runner
+            .run("flaky", move || {
+                sleep(Duration::from_millis(10));
+                flaky()
+            })
+            .unwrap();
+
+        let summary = par_runner.into_wait();
+        assert_eq!(summary.results.len(), 1);
+        assert!(summary.results[0].duration >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn summary_by_duration_is_fastest_first() {
+        let mut par_runner = ParRunner::<&str, _>::with_parallel(2, NullProgressListener);
+
+        for (name, delay) in [("slow", 30), ("fast", 1)] {
+            par_runner
+                .run(name, move || {
+                    sleep(Duration::from_millis(delay));
+                    Ok(())
+                })
+                .unwrap();
+        }
+
+        let summary = par_runner.into_wait();
+        let names = summary
+            .by_duration()
+            .into_iter()
+            .map(|r| r.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["fast", "slow"]);
+    }
+
+    #[test]
+    fn runs_dependent_after_its_dependency() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener);
+
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        let clone = Arc::clone(&finished);
+        par_runner
+            .run("slow", move || {
+                sleep(Duration::from_millis(20));
+                clone.lock().unwrap().push("slow");
+                Ok::<_, &str>(())
+            })
+            .unwrap();
+        let clone = Arc::clone(&finished);
+        par_runner
+            .run_after("dependent", &[String::from("slow")], move || {
+                clone.lock().unwrap().push("dependent");
+                Ok(())
+            })
+            .unwrap();
+        let clone = Arc::clone(&finished);
+        par_runner
+            .run("fast", move || {
+                clone.lock().unwrap().push("fast");
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(par_runner.into_wait().into_failures(), vec![]);
+        assert_eq!(*finished.lock().unwrap(), vec!["fast", "slow", "dependent"]);
+    }
+
+    #[test]
+    fn running_weight_stays_within_capacity() {
+        let mut par_runner = ParRunner::with_parallel(3, NullProgressListener);
+
+        let load = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let huge_alongside = Arc::new(AtomicUsize::new(usize::MAX));
+        for (name, weight) in [("heavy", 2), ("light", 1), ("other", 1), ("huge", 10)] {
+            let (load, peak) = (Arc::clone(&load), Arc::clone(&peak));
+            let huge_alongside = Arc::clone(&huge_alongside);
+            par_runner
+                .run_weighted(name, weight, &[], move || {
+                    let others = load.fetch_add(weight, Ordering::SeqCst);
+                    if weight > 3 {
+                        huge_alongside.store(others, Ordering::SeqCst);
+                    } else {
+                        peak.fetch_max(others + weight, Ordering::SeqCst);
+                    }
+                    sleep(Duration::from_millis(10));
+                    load.fetch_sub(weight, Ordering::SeqCst);
+                    Ok::<_, &str>(())
+                })
+                .unwrap();
+        }
+
+        assert_eq!(par_runner.into_wait().into_failures(), vec![]);
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        // A task heavier than the whole runner still runs, but only once nothing else is.
+        assert_eq!(huge_alongside.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn labeled_results_record_action_and_address() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener);
+        par_runner
+            .run_labeled("test //a #1", "test", "//a", 1, &[], || Ok::<_, &str>(()))
+            .unwrap();
+        par_runner.run("plain", || Ok(())).unwrap();
+
+        let summary = par_runner.into_wait();
+        let labels = summary
+            .results
+            .iter()
+            .map(|r| (r.name.as_str(), r.action.as_str(), r.address.as_str()))
+            .collect::<HashSet<_>>();
+        assert_eq!(
+            labels,
+            HashSet::from([("test //a #1", "test", "//a"), ("plain", "", "")])
+        );
+    }
+
+    #[test]
+    fn dependency_failure_fails_dependents_without_running_them() {
+        let mut par_runner = ParRunner::with_parallel(2, NullProgressListener).keep_going();
+
+        let ran = Arc::new(AtomicUsize::new(0));
+        par_runner.run("fails", || Err("failed")).unwrap();
+        let clone = Arc::clone(&ran);
+        par_runner
+            .run_after("dependent", &[String::from("fails")], move || {
+                clone.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .unwrap();
+        let clone = Arc::clone(&ran);
+        par_runner
+            .run_after("transitive", &[String::from("dependent")], move || {
+                clone.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(
+            par_runner.into_wait().into_failures(),
+            vec![
+                (String::from("fails"), TaskError::Failed("failed")),
+                (
+                    String::from("dependent"),
+                    TaskError::DependencyFailed(String::from("fails"))
+                ),
+                (
+                    String::from("transitive"),
+                    TaskError::DependencyFailed(String::from("dependent"))
+                ),
+            ]
+        );
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
     }
 }
@@ -0,0 +1,212 @@
+use serde_json::{json, Value};
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use gentle::multi_runner::RunSummary;
+
+/// Longest to wait on the collector, so an unreachable one doesn't hold up the end of a run.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Exports a span per task, under one span for the whole run, to the OTLP/HTTP collector at
+/// `OTEL_EXPORTER_OTLP_ENDPOINT`.
+pub struct Exporter {
+    endpoint: String,
+    /// From `OTEL_EXPORTER_OTLP_HEADERS`, e.g. `x-honeycomb-team=...`.
+    headers: Vec<(String, String)>,
+    service: String,
+    trace_id: String,
+    run_id: String,
+    started: SystemTime,
+}
+
+impl Exporter {
+    /// An exporter for a run starting now, if `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+    pub fn from_env() -> Option<Self> {
+        let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .filter(|endpoint| !endpoint.is_empty())?;
+        let headers = std::env::var("OTEL_EXPORTER_OTLP_HEADERS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|header| header.split_once('='))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        let service = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "gentle".into());
+
+        Some(Self::new(endpoint, headers, service))
+    }
+
+    fn new(endpoint: String, headers: Vec<(String, String)>, service: String) -> Self {
+        Exporter {
+            endpoint,
+            headers,
+            service,
+            trace_id: random_id(16),
+            run_id: random_id(8),
+            started: SystemTime::now(),
+        }
+    }
+
+    /// Exports the spans of the finished run. Failing to isn't worth failing the run over, so
+    /// errors are only printed.
+    pub fn finish<E: Display>(&self, summary: &RunSummary<E>) {
+        if let Err(e) = self.export(summary) {
+            eprintln!("Failed to export spans to {}: {e}", self.endpoint);
+        }
+    }
+
+    fn export<E: Display>(&self, summary: &RunSummary<E>) -> anyhow::Result<()> {
+        let mut request = ureq::post(&format!(
+            "{}/v1/traces",
+            self.endpoint.trim_end_matches('/')
+        ))
+        .timeout(EXPORT_TIMEOUT)
+        .set("Content-Type", "application/json");
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        request.send_string(&self.payload(summary).to_string())?;
+        Ok(())
+    }
+
+    /// A span for the run and each task that ran, as an OTLP `ExportTraceServiceRequest`.
+    fn payload<E: Display>(&self, summary: &RunSummary<E>) -> Value {
+        let now = SystemTime::now();
+        let run = json!({
+            "traceId": self.trace_id,
+            "spanId": self.run_id,
+            "name": "gentle",
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(self.started),
+            "endTimeUnixNano": unix_nanos(now),
+        });
+        // Tasks whose dependencies failed never started, so have nothing to show.
+        let tasks = summary
+            .results
+            .iter()
+            .filter(|r| r.slot.is_some())
+            .map(|result| {
+                let started = now - result.started.elapsed();
+                let mut span = json!({
+                    "traceId": self.trace_id,
+                    "spanId": random_id(8),
+                    "parentSpanId": self.run_id,
+                    "name": result.name,
+                    "kind": 1,
+                    "startTimeUnixNano": unix_nanos(started),
+                    "endTimeUnixNano": unix_nanos(started + result.duration),
+                    "attributes": [
+                        attribute("gentle.address", &result.address),
+                        attribute("gentle.action", &result.action),
+                        attribute(
+                            "gentle.outcome",
+                            if result.result.is_ok() { "passed" } else { "failed" },
+                        ),
+                        json!({
+                            "key": "gentle.duration_ms",
+                            "value": { "intValue": result.duration.as_millis().to_string() },
+                        }),
+                    ],
+                });
+                if let Err(error) = &result.result {
+                    span["status"] = json!({ "code": 2, "message": error.to_string() });
+                }
+                span
+            });
+        let spans = std::iter::once(run).chain(tasks).collect::<Vec<_>>();
+
+        json!({
+            "resourceSpans": [{
+                "resource": { "attributes": [attribute("service.name", &self.service)] },
+                "scopeSpans": [{ "scope": { "name": "gentle" }, "spans": spans }],
+            }],
+        })
+    }
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    since_epoch.as_nanos().to_string()
+}
+
+/// `bytes` of hex that won't repeat within or across runs.
+fn random_id(bytes: usize) -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&std::process::id().to_le_bytes());
+    hasher.update(unix_nanos(SystemTime::now()).as_bytes());
+    hasher.update(&NEXT.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    hasher.finalize().to_hex()[..bytes * 2].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gentle::multi_runner::{TaskError, TaskResult};
+    use std::time::Instant;
+
+    #[test]
+    fn spans_are_parented_under_the_run() {
+        let exporter = Exporter::new(String::new(), Vec::new(), String::from("gentle"));
+        let summary = RunSummary {
+            results: vec![
+                TaskResult {
+                    name: String::from("test //a:rust_crate"),
+                    action: String::from("test"),
+                    address: String::from("//a:rust_crate"),
+                    started: Instant::now(),
+                    duration: Duration::from_millis(20),
+                    slot: Some(0),
+                    result: Ok(()),
+                },
+                TaskResult {
+                    name: String::from("build //b:go_mod"),
+                    action: String::from("build"),
+                    address: String::from("//b:go_mod"),
+                    started: Instant::now(),
+                    duration: Duration::from_millis(5),
+                    slot: Some(1),
+                    result: Err(TaskError::Failed("broken")),
+                },
+                TaskResult {
+                    name: String::from("test //b:go_mod"),
+                    action: String::from("test"),
+                    address: String::from("//b:go_mod"),
+                    started: Instant::now(),
+                    duration: Duration::ZERO,
+                    slot: None,
+                    result: Err(TaskError::DependencyFailed(String::from(
+                        "build //b:go_mod",
+                    ))),
+                },
+            ],
+        };
+
+        let payload = exporter.payload(&summary);
+        let spans = payload["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        assert_eq!(spans.len(), 3);
+        for span in &spans[1..] {
+            assert_eq!(span["traceId"], spans[0]["traceId"]);
+            assert_eq!(span["parentSpanId"], spans[0]["spanId"]);
+        }
+        assert_eq!(
+            spans[1]["attributes"][0]["value"]["stringValue"],
+            "//a:rust_crate"
+        );
+        assert_eq!(spans[1]["attributes"][1]["value"]["stringValue"], "test");
+        assert_eq!(spans[1]["attributes"][2]["value"]["stringValue"], "passed");
+        assert_eq!(spans[2]["attributes"][2]["value"]["stringValue"], "failed");
+        assert_eq!(spans[2]["status"]["message"], "broken");
+    }
+}
@@ -0,0 +1,128 @@
+use serde::Serialize;
+use std::path::Path;
+
+use gentle::multi_runner::RunSummary;
+
+/// A complete event in the Chrome trace event format, read by `chrome://tracing` and Perfetto.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct Event<'s> {
+    name: &'s str,
+    cat: &'s str,
+    ph: &'static str,
+    /// Microseconds since the first task started.
+    ts: u128,
+    dur: u128,
+    pid: u32,
+    /// The runner slot, so each row of the timeline is one slot.
+    tid: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace<'s> {
+    trace_events: Vec<Event<'s>>,
+    display_time_unit: &'static str,
+}
+
+/// Writes a timeline of when each task ran, and in which slot, as a Chrome trace.
+pub fn write_report<E>(path: &Path, summary: &RunSummary<E>) -> anyhow::Result<()> {
+    let trace = Trace {
+        trace_events: events(summary),
+        display_time_unit: "ms",
+    };
+    std::fs::write(path, serde_json::to_vec(&trace)?)?;
+    Ok(())
+}
+
+fn events<E>(summary: &RunSummary<E>) -> Vec<Event<'_>> {
+    let Some(first) = summary.results.iter().map(|r| r.started).min() else {
+        return Vec::new();
+    };
+
+    summary
+        .results
+        .iter()
+        // Tasks that never ran, like those whose dependencies failed, take no time anywhere.
+        .filter_map(|result| {
+            Some(Event {
+                name: &result.name,
+                cat: &result.action,
+                ph: "X",
+                ts: (result.started - first).as_micros(),
+                dur: result.duration.as_micros(),
+                pid: 1,
+                tid: result.slot?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gentle::multi_runner::{TaskError, TaskResult};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn one_event_per_task_that_ran() {
+        let started = Instant::now();
+        let summary = RunSummary::<()> {
+            results: vec![
+                TaskResult {
+                    name: String::from("test //a:rust_crate"),
+                    action: String::from("test"),
+                    address: String::from("//a:rust_crate"),
+                    started,
+                    duration: Duration::from_millis(1500),
+                    slot: Some(0),
+                    result: Ok(()),
+                },
+                TaskResult {
+                    name: String::from("build //b:go_mod"),
+                    action: String::from("build"),
+                    address: String::from("//b:go_mod"),
+                    started: started + Duration::from_millis(20),
+                    duration: Duration::from_millis(5),
+                    slot: Some(1),
+                    result: Err(TaskError::Failed(())),
+                },
+                TaskResult {
+                    name: String::from("test //b:go_mod"),
+                    action: String::from("test"),
+                    address: String::from("//b:go_mod"),
+                    started: started + Duration::from_millis(25),
+                    duration: Duration::ZERO,
+                    slot: None,
+                    result: Err(TaskError::DependencyFailed(String::from(
+                        "build //b:go_mod",
+                    ))),
+                },
+            ],
+        };
+
+        assert_eq!(
+            events(&summary),
+            vec![
+                Event {
+                    name: "test //a:rust_crate",
+                    cat: "test",
+                    ph: "X",
+                    ts: 0,
+                    dur: 1_500_000,
+                    pid: 1,
+                    tid: 0,
+                },
+                Event {
+                    name: "build //b:go_mod",
+                    cat: "build",
+                    ph: "X",
+                    ts: 20_000,
+                    dur: 5_000,
+                    pid: 1,
+                    tid: 1,
+                },
+            ]
+        );
+    }
+}
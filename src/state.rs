@@ -0,0 +1,132 @@
+use serde::*;
+use std::{collections::HashMap, path::Path};
+
+use gentle::multi_runner::RunSummary;
+
+pub const STATE_FILE: &str = ".gentle-state.json";
+
+/// What gentle remembers between runs in the working directory.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct State {
+    /// Tasks, by name, that passed when they last ran, with their input fingerprint at the time
+    /// if they have one.
+    #[serde(default)]
+    pub passed: HashMap<String, Option<String>>,
+}
+
+impl State {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| anyhow::anyhow!("parsing {}: {e}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Remembers tasks that passed, with their fingerprints, and forgets tasks that failed.
+    /// Returns whether anything changed.
+    pub fn record<E>(
+        &mut self,
+        summary: &RunSummary<E>,
+        fingerprints: &HashMap<String, String>,
+    ) -> bool {
+        let mut changed = false;
+
+        for result in &summary.results {
+            if result.result.is_err() {
+                changed |= self.passed.remove(&result.name).is_some();
+                continue;
+            }
+
+            let fingerprint = fingerprints.get(&result.name).cloned();
+            if self.passed.get(&result.name) != Some(&fingerprint) {
+                self.passed.insert(result.name.clone(), fingerprint);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gentle::multi_runner::{TaskError, TaskResult};
+    use std::time::{Duration, Instant};
+
+    fn result(name: &str, passed: bool) -> TaskResult<()> {
+        TaskResult {
+            name: name.to_string(),
+            action: String::new(),
+            address: String::new(),
+            started: Instant::now(),
+            duration: Duration::ZERO,
+            slot: None,
+            result: if passed {
+                Ok(())
+            } else {
+                Err(TaskError::Failed(()))
+            },
+        }
+    }
+
+    #[test]
+    fn records_passed_and_forgets_failed() {
+        let mut state = State::default();
+        state.passed.insert("test //b:b".into(), Some("old".into()));
+
+        let summary = RunSummary {
+            results: vec![
+                result("test //a:a", true),
+                result("test //b:b", false),
+                result("test //c:c", true),
+            ],
+        };
+        let fingerprints = [
+            ("test //a:a".to_string(), "a".to_string()),
+            ("test //b:b".to_string(), "b".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert!(state.record(&summary, &fingerprints));
+        assert_eq!(
+            state.passed,
+            [
+                ("test //a:a".to_string(), Some("a".to_string())),
+                ("test //c:c".to_string(), None),
+            ]
+            .into_iter()
+            .collect()
+        );
+
+        assert!(!state.record(&summary, &fingerprints));
+    }
+
+    #[test]
+    fn round_trips_through_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        assert_eq!(State::load(&path).unwrap(), State::default());
+
+        let mut state = State::default();
+        state.passed.insert("test //a:a".into(), Some("a".into()));
+        state.passed.insert("test //b:b".into(), None);
+        state.save(&path).unwrap();
+
+        assert_eq!(State::load(&path).unwrap(), state);
+    }
+}
@@ -0,0 +1,237 @@
+use serde::Deserialize;
+use std::{fmt::Display, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TargetAddress {
+    pub package: String,
+    pub identifier: String,
+}
+
+impl Display for TargetAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "//{}:{}", self.package, self.identifier)
+    }
+}
+
+impl FromStr for TargetAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix("//")
+            .ok_or_else(|| anyhow::anyhow!("target address must start with '//': {s:?}"))?;
+        let (package, identifier) = rest
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("target address missing ':identifier': {s:?}"))?;
+
+        Ok(TargetAddress {
+            package: package.to_string(),
+            identifier: identifier.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TargetMatcher {
+    package: String,
+    identifier: Option<String>,
+}
+
+impl TargetMatcher {
+    pub fn matches(&self, address: &TargetAddress) -> bool {
+        let package_matches = match self.package.strip_suffix("...") {
+            Some("") => true,
+            Some(prefix) if prefix.ends_with('/') => {
+                let parent = &prefix[..prefix.len() - 1];
+                address.package == parent || address.package.starts_with(prefix)
+            }
+            _ => self.package == address.package,
+        };
+        let identifier_matches = match &self.identifier {
+            None => true,
+            Some(identifier) => match identifier.strip_suffix('*') {
+                Some(prefix) => address.identifier.starts_with(prefix),
+                None => identifier == &address.identifier,
+            },
+        };
+
+        package_matches && identifier_matches
+    }
+}
+
+impl FromStr for TargetMatcher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix("//")
+            .ok_or_else(|| anyhow::anyhow!("target matcher must start with '//': {s:?}"))?;
+
+        let (package, identifier) = match rest.rsplit_once(':') {
+            Some((package, identifier)) => (package, Some(identifier.to_string())),
+            None => (rest, None),
+        };
+
+        Ok(TargetMatcher {
+            package: package.trim_end_matches('/').to_string(),
+            identifier,
+        })
+    }
+}
+
+impl TryFrom<String> for TargetMatcher {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for TargetMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "//{}", self.package)?;
+        if let Some(identifier) = &self.identifier {
+            write!(f, ":{identifier}")?;
+        }
+        Ok(())
+    }
+}
+
+pub trait Matches {
+    fn matches(&self, address: &TargetAddress) -> bool;
+}
+
+impl Matches for [TargetMatcher] {
+    fn matches(&self, address: &TargetAddress) -> bool {
+        self.iter().any(|m| m.matches(address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(s: &str) -> TargetAddress {
+        s.parse().unwrap()
+    }
+
+    fn matcher(s: &str) -> TargetMatcher {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_address() {
+        assert_eq!(
+            address("//foo/bar:rust_crate"),
+            TargetAddress {
+                package: String::from("foo/bar"),
+                identifier: String::from("rust_crate"),
+            }
+        );
+    }
+
+    #[test]
+    fn address_round_trips_through_display() {
+        assert_eq!(address("//foo:go_mod").to_string(), "//foo:go_mod");
+        assert_eq!(address("//:go_mod").to_string(), "//:go_mod");
+    }
+
+    #[test]
+    fn address_requires_identifier() {
+        assert!("//foo".parse::<TargetAddress>().is_err());
+        assert!("foo:bar".parse::<TargetAddress>().is_err());
+    }
+
+    #[test]
+    fn all_matches_everything() {
+        let m = matcher("//...");
+
+        assert!(m.matches(&address("//:rust_crate")));
+        assert!(m.matches(&address("//foo/bar:go_mod")));
+    }
+
+    #[test]
+    fn exact_match() {
+        let m = matcher("//backend/api:rust_crate");
+
+        assert!(m.matches(&address("//backend/api:rust_crate")));
+        assert!(!m.matches(&address("//backend/api:go_mod")));
+        assert!(!m.matches(&address("//backend:rust_crate")));
+    }
+
+    #[test]
+    fn package_without_identifier_matches_all_identifiers() {
+        let m = matcher("//backend");
+
+        assert!(m.matches(&address("//backend:rust_crate")));
+        assert!(m.matches(&address("//backend:go_mod")));
+        assert!(!m.matches(&address("//backend/api:go_mod")));
+    }
+
+    #[test]
+    fn recursive_matches_nested_packages() {
+        let m = matcher("//backend/...");
+
+        assert!(m.matches(&address("//backend/api:rust_crate")));
+        assert!(m.matches(&address("//backend/api/v2:go_mod")));
+        assert!(!m.matches(&address("//frontend:node")));
+    }
+
+    #[test]
+    fn recursive_matches_its_own_package() {
+        let m = matcher("//backend/...");
+
+        assert!(m.matches(&address("//backend:rust_crate")));
+    }
+
+    #[test]
+    fn recursive_does_not_match_sibling_with_shared_prefix() {
+        let m = matcher("//backend/...");
+
+        assert!(!m.matches(&address("//backends:rust_crate")));
+        assert!(!m.matches(&address("//backends/api:rust_crate")));
+    }
+
+    #[test]
+    fn recursive_with_identifier() {
+        let m = matcher("//backend/...:go_mod");
+
+        assert!(m.matches(&address("//backend/api:go_mod")));
+        assert!(!m.matches(&address("//backend/api:rust_crate")));
+    }
+
+    #[test]
+    fn identifier_star_matches_any_identifier() {
+        let m = matcher("//foo/bar:*");
+
+        assert!(m.matches(&address("//foo/bar:rust_crate")));
+        assert!(m.matches(&address("//foo/bar:go_mod")));
+        assert!(!m.matches(&address("//foo:go_mod")));
+    }
+
+    #[test]
+    fn identifier_prefix_glob() {
+        let m = matcher("//foo:rust_*");
+
+        assert!(m.matches(&address("//foo:rust_crate")));
+        assert!(m.matches(&address("//foo:rust_doctest")));
+        assert!(!m.matches(&address("//foo:go_mod")));
+    }
+
+    #[test]
+    fn identifier_without_wildcard_is_exact() {
+        let m = matcher("//foo:rust");
+
+        assert!(!m.matches(&address("//foo:rust_crate")));
+    }
+
+    #[test]
+    fn list_matches_any() {
+        let matchers = [matcher("//foo"), matcher("//bar:go_mod")];
+
+        assert!(matchers.matches(&address("//foo:rust_crate")));
+        assert!(matchers.matches(&address("//bar:go_mod")));
+        assert!(!matchers.matches(&address("//bar:rust_crate")));
+    }
+}
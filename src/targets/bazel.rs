@@ -0,0 +1,187 @@
+use super::*;
+
+use serde::Deserialize;
+
+const BUILD_FILES: &[&str] = &["BUILD.bazel", "BUILD"];
+const WORKSPACE_FILES: &[&str] = &["MODULE.bazel", "WORKSPACE.bazel", "WORKSPACE"];
+
+/// `bazel test` exits with this when the build passed but there were no tests to run.
+const NO_TESTS: i32 = 4;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "bazel",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    if !any_exists(path, BUILD_FILES)? {
+        return Ok(Vec::new());
+    }
+    // A BUILD file outside a workspace isn't something Bazel can build.
+    let Some(workspace) = workspace(path)? else {
+        return Ok(Vec::new());
+    };
+
+    let config = package_config::<PackageConfig>(&workspace)?.bazel;
+    Ok(vec![Box::new(BazelTarget::new(path, &workspace, config)?)])
+}
+
+/// The root of the Bazel workspace containing `path`.
+fn workspace(path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    for ancestor in path.ancestors() {
+        if any_exists(ancestor, WORKSPACE_FILES)? {
+            return Ok(Some(ancestor.to_path_buf()));
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Deserialize, Default)]
+struct PackageConfig {
+    #[serde(default)]
+    bazel: BazelConfig,
+}
+
+/// The `[bazel]` section of the workspace root's `gentle.toml`.
+#[derive(Deserialize, Default, Clone, Debug, PartialEq, Eq)]
+struct BazelConfig {
+    /// Run Bazel's tasks alone, since Bazel already runs its own actions in parallel.
+    #[serde(default)]
+    exclusive: bool,
+}
+
+/// A single Bazel package, tested without the packages nested in it since they're targets of
+/// their own.
+pub struct BazelTarget {
+    path: PathBuf,
+    workspace: PathBuf,
+    /// The package's label, e.g. `//services/api`.
+    label: String,
+    config: BazelConfig,
+}
+
+impl BazelTarget {
+    fn new(path: &Path, workspace: &Path, config: BazelConfig) -> anyhow::Result<Self> {
+        let relative = path.strip_prefix(workspace)?;
+        let components = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            path: path.into(),
+            workspace: workspace.into(),
+            label: format!("//{}", components.join("/")),
+            config,
+        })
+    }
+
+    fn bazel(&self, ctx: &TaskContext, subcommand: &str) -> Command {
+        let color = if ctx.color {
+            "--color=yes"
+        } else {
+            "--color=no"
+        };
+        let mut command = Command::new("bazel");
+        command
+            .args([subcommand, color, &format!("{}:all", self.label)])
+            .current_dir(&self.workspace);
+        command
+    }
+}
+
+impl Display for BazelTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for BazelTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("bazel"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        let out = ctx.output(&mut self.bazel(ctx, "test"))?;
+        if out.status.code() == Some(NO_TESTS) {
+            return Ok(());
+        }
+        out.succeeded()
+    }
+
+    fn perform_build(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(&mut self.bazel(ctx, "build"))
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        // A symlink into Bazel's output base, outside the workspace.
+        std::fs::canonicalize(self.workspace.join("bazel-out"))
+            .into_iter()
+            .collect()
+    }
+
+    fn weight(&self) -> u32 {
+        if self.config.exclusive {
+            // More than any runner's capacity, so nothing runs alongside it.
+            u32::MAX
+        } else {
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packages_are_labelled_from_the_workspace_root() {
+        let (dir, _) = discover_files(
+            discover,
+            &[
+                ("MODULE.bazel", ""),
+                ("BUILD.bazel", ""),
+                ("services/api/BUILD", ""),
+            ],
+        );
+        let api = dir.path().join("services/api");
+
+        let root = BazelTarget::new(dir.path(), dir.path(), BazelConfig::default()).unwrap();
+        assert_eq!(root.label, "//");
+        let target = discover(&api, &Discovery::default()).unwrap();
+        assert_eq!(target.len(), 1);
+        assert_eq!(target[0].address().identifier, "bazel");
+        assert_eq!(
+            BazelTarget::new(&api, dir.path(), BazelConfig::default())
+                .unwrap()
+                .label,
+            "//services/api"
+        );
+    }
+
+    #[test]
+    fn build_file_outside_a_workspace_is_skipped() {
+        assert!(discover_files(discover, &[("BUILD", "")]).1.is_empty());
+    }
+
+    #[test]
+    fn exclusive_config_runs_alone() {
+        let (_dir, targets) = discover_files(
+            discover,
+            &[
+                ("WORKSPACE", ""),
+                ("BUILD", ""),
+                ("gentle.toml", "[bazel]\nexclusive = true\n"),
+            ],
+        );
+        assert_eq!(targets[0].weight(), u32::MAX);
+    }
+}
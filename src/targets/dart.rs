@@ -0,0 +1,130 @@
+use super::*;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "dart",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    let pubspec = path.join("pubspec.yaml");
+    if !pubspec.try_exists()? {
+        return Ok(Vec::new());
+    }
+    // `dart test` fails without any tests to run.
+    if !path.join("test").is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let flutter = depends_on_flutter(&std::fs::read_to_string(&pubspec)?);
+    Ok(vec![Box::new(DartTarget::new(path, flutter))])
+}
+
+/// Whether the `pubspec.yaml` in `pubspec` lists `flutter` under its top-level `dependencies`.
+/// Only that much YAML is understood, as written by `flutter create`:
+///
+/// ```yaml
+/// dependencies:
+///   flutter:
+///     sdk: flutter
+/// ```
+fn depends_on_flutter(pubspec: &str) -> bool {
+    let mut in_dependencies = false;
+    for line in pubspec.lines() {
+        let content = line.split('#').next().unwrap_or_default().trim_end();
+        if content.trim().is_empty() {
+            continue;
+        }
+
+        let indented = content.starts_with([' ', '\t']);
+        if !indented {
+            in_dependencies = content == "dependencies:";
+            continue;
+        }
+        if in_dependencies && content.trim_start().starts_with("flutter:") {
+            return true;
+        }
+    }
+
+    false
+}
+
+pub struct DartTarget {
+    path: PathBuf,
+    flutter: bool,
+}
+
+impl DartTarget {
+    fn new(path: &Path, flutter: bool) -> Self {
+        Self {
+            path: path.into(),
+            flutter,
+        }
+    }
+}
+
+impl Display for DartTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for DartTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("dart"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        let program = if self.flutter { "flutter" } else { "dart" };
+        ctx.run(Command::new(program).arg("test").current_dir(&self.path))
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        [self.path.join(".dart_tool"), self.path.join("build")]
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flutter_dependency_is_detected() {
+        let flutter = "name: app\n\
+                       dependencies:\n  \
+                         flutter:\n    \
+                           sdk: flutter\n\
+                       dev_dependencies:\n  \
+                         test: ^1.24.0\n";
+        assert!(depends_on_flutter(flutter));
+
+        let dart = "name: lib # flutter: not really\n\
+                    dependencies:\n  \
+                      http: ^1.1.0\n\
+                    dev_dependencies:\n  \
+                      flutter_lints: ^2.0.0\n";
+        assert!(!depends_on_flutter(dart));
+    }
+
+    #[test]
+    fn package_without_tests_is_skipped() {
+        assert!(discover_files(discover, &[("pubspec.yaml", "name: lib\n")])
+            .1
+            .is_empty());
+
+        let (_dir, targets) = discover_files(
+            discover,
+            &[("pubspec.yaml", "name: lib\n"), ("test/lib_test.dart", "")],
+        );
+        assert_eq!(targets[0].address().identifier, "dart");
+    }
+}
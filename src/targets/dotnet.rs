@@ -0,0 +1,169 @@
+use super::*;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "dotnet",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    // `dotnet` refuses to guess between several, so the target names the one to use.
+    if let Some(solution) = files_with_extension(path, "sln")?.into_iter().next() {
+        let mut dirs = vec![path.to_path_buf()];
+        dirs.extend(
+            solution_projects(&solution)?
+                .into_iter()
+                .map(|p| path.join(p)),
+        );
+        return Ok(vec![Box::new(DotnetTarget::new(path, solution, dirs))]);
+    }
+
+    let Some(project) = files_with_extension(path, "csproj")?.into_iter().next() else {
+        return Ok(Vec::new());
+    };
+    // Projects in a solution are tested by the solution's target.
+    if in_solution(path)? {
+        return Ok(Vec::new());
+    }
+
+    Ok(vec![Box::new(DotnetTarget::new(
+        path,
+        project,
+        vec![path.to_path_buf()],
+    ))])
+}
+
+/// Directories of the projects in the solution at `solution`, relative to it. Each is listed on a
+/// line like `Project("{...}") = "Api", "src\Api\Api.csproj", "{...}"`.
+fn solution_projects(solution: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let contents = std::fs::read_to_string(solution)?;
+    Ok(contents
+        .lines()
+        .filter(|line| line.starts_with("Project("))
+        .filter_map(|line| line.split('"').nth(5))
+        .filter(|project| project.ends_with("proj"))
+        .map(|project| {
+            let project = PathBuf::from(project.replace('\\', "/"));
+            project.parent().map(Path::to_path_buf).unwrap_or_default()
+        })
+        .collect())
+}
+
+/// Whether a solution in `path` or an ancestor lists the project at `path`.
+fn in_solution(path: &Path) -> anyhow::Result<bool> {
+    for ancestor in path.ancestors().skip(1) {
+        let relative = path.strip_prefix(ancestor)?;
+        for solution in files_with_extension(ancestor, "sln")? {
+            if solution_projects(&solution)?.iter().any(|p| p == relative) {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+fn nuget_packages() -> PathBuf {
+    std::env::var("NUGET_PACKAGES")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            Path::new(&std::env::var("HOME").unwrap_or(String::from("/"))).join(".nuget/packages")
+        })
+}
+
+pub struct DotnetTarget {
+    path: PathBuf,
+    /// The `.sln` or `.csproj` to build.
+    file: PathBuf,
+    /// Directories of this project, or of the solution and each of its projects.
+    dirs: Vec<PathBuf>,
+}
+
+impl DotnetTarget {
+    fn new(path: &Path, file: PathBuf, dirs: Vec<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            file,
+            dirs,
+        }
+    }
+
+    fn command(&self, subcommand: &str) -> Command {
+        let mut command = Command::new("dotnet");
+        command
+            .arg(subcommand)
+            .arg(self.file.file_name().unwrap())
+            .current_dir(&self.path);
+        command
+    }
+}
+
+impl Display for DotnetTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for DotnetTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("dotnet"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(&mut self.command("test"))
+    }
+
+    fn perform_build(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(&mut self.command("build"))
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        self.dirs
+            .iter()
+            .flat_map(|dir| [dir.join("bin"), dir.join("obj")])
+            .chain([nuget_packages()])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOLUTION: &str = r#"
+Microsoft Visual Studio Solution File, Format Version 12.00
+Project("{FAE04EC0-301F-11D3-BF4B-00C04F79EFBC}") = "Api", "src\Api\Api.csproj", "{1A2B}"
+EndProject
+Global
+EndGlobal
+"#;
+
+    #[test]
+    fn solution_is_preferred_over_its_projects() {
+        let (dir, root) = discover_files(
+            discover,
+            &[
+                ("App.sln", SOLUTION),
+                ("src/Api/Api.csproj", ""),
+                ("tools/Gen/Gen.csproj", ""),
+            ],
+        );
+        let api = dir.path().join("src/Api");
+        let tool = dir.path().join("tools/Gen");
+
+        assert_eq!(root.len(), 1);
+        assert_eq!(root[0].address().identifier, "dotnet");
+        assert!(root[0].cache_paths().contains(&api.join("obj")));
+
+        assert!(discover(&api, &Discovery::default()).unwrap().is_empty());
+        // Not in the solution, so it's its own target.
+        assert_eq!(discover(&tool, &Discovery::default()).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,180 @@
+use super::*;
+
+use serde::Deserialize;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "elixir",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    if !path.join("mix.exs").try_exists()? {
+        return Ok(Vec::new());
+    }
+    // Apps of an umbrella project are tested by the umbrella's target.
+    if in_umbrella(path)? {
+        return Ok(Vec::new());
+    }
+
+    let config = package_config::<PackageConfig>(path)?.elixir;
+    Ok(vec![Box::new(ElixirTarget::new(path, config))])
+}
+
+#[derive(Deserialize, Default)]
+struct PackageConfig {
+    #[serde(default)]
+    elixir: ElixirConfig,
+}
+
+/// The `[elixir]` section of a project's `gentle.toml`.
+#[derive(Deserialize, Default, Clone, Debug, PartialEq, Eq)]
+struct ElixirConfig {
+    /// Run `mix deps.get` before testing, for when dependencies aren't fetched separately.
+    #[serde(default)]
+    deps_get: bool,
+}
+
+/// Where the umbrella project defined in `mix_exs` keeps its apps, if it's one. Umbrellas set
+/// `apps_path: "apps"` in their `project`.
+fn apps_path(mix_exs: &str) -> Option<&str> {
+    let (_, rest) = mix_exs.split_once("apps_path:")?;
+    rest.trim_start().strip_prefix('"')?.split('"').next()
+}
+
+/// Whether an ancestor of `path` is an umbrella project with `path` among its apps.
+fn in_umbrella(path: &Path) -> anyhow::Result<bool> {
+    for ancestor in path.ancestors().skip(1) {
+        let mix_exs = ancestor.join("mix.exs");
+        if !mix_exs.try_exists()? {
+            continue;
+        }
+
+        let mix_exs = std::fs::read_to_string(mix_exs)?;
+        let Some(apps) = apps_path(&mix_exs) else {
+            continue;
+        };
+        if path.strip_prefix(ancestor)?.starts_with(apps) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+pub struct ElixirTarget {
+    path: PathBuf,
+    config: ElixirConfig,
+}
+
+impl ElixirTarget {
+    fn new(path: &Path, config: ElixirConfig) -> Self {
+        Self {
+            path: path.into(),
+            config,
+        }
+    }
+
+    fn mix(&self, ctx: &TaskContext, args: &[&str]) -> anyhow::Result<()> {
+        ctx.run(Command::new("mix").args(args).current_dir(&self.path))
+    }
+}
+
+impl Display for ElixirTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for ElixirTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("elixir"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        if self.config.deps_get {
+            self.mix(ctx, &["deps.get"])?;
+        }
+        self.mix(ctx, &["test"])
+    }
+
+    fn perform_build(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        if self.config.deps_get {
+            self.mix(ctx, &["deps.get"])?;
+        }
+        self.mix(ctx, &["compile"])
+    }
+
+    fn perform_fmt_check(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        self.mix(ctx, &["format", "--check-formatted"])
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        [self.path.join("_build"), self.path.join("deps")]
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UMBRELLA: &str = r#"
+defmodule Shop.MixProject do
+  use Mix.Project
+
+  def project do
+    [apps_path: "apps", version: "0.1.0", deps: deps()]
+  end
+end
+"#;
+
+    #[test]
+    fn umbrella_apps_are_tested_by_the_umbrella() {
+        let (dir, umbrella) = discover_files(
+            discover,
+            &[("mix.exs", UMBRELLA), ("apps/cart/mix.exs", "")],
+        );
+
+        assert_eq!(umbrella.len(), 1);
+        assert_eq!(umbrella[0].address().identifier, "elixir");
+        assert!(
+            discover(&dir.path().join("apps/cart"), &Discovery::default())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn projects_nested_elsewhere_are_their_own_targets() {
+        let (dir, _) = discover_files(
+            discover,
+            &[("mix.exs", UMBRELLA), ("tools/gen/mix.exs", "")],
+        );
+
+        let tool = dir.path().join("tools/gen");
+        assert_eq!(discover(&tool, &Discovery::default()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn deps_get_is_opt_in() {
+        let deps_get = |files| {
+            let (dir, _) = discover_files(discover, files);
+            package_config::<PackageConfig>(dir.path())
+                .unwrap()
+                .elixir
+                .deps_get
+        };
+
+        assert!(!deps_get(&[]));
+        assert!(deps_get(&[("gentle.toml", "[elixir]\ndeps_get = true\n")]));
+    }
+}
@@ -1,55 +1,580 @@
 use super::*;
 
+use serde::Deserialize;
+
+use crate::coverage::Lcov;
+
 #[linkme::distributed_slice(TARGET_DISCOVERY)]
-fn discover(path: &Path) -> anyhow::Result<Vec<Box<dyn Target>>> {
-    if path.join("go.mod").try_exists()? {
-        Ok(vec![(Box::new(GoModTarget::new(&path)))])
-    } else {
-        Ok(Vec::new())
+static FACTORY: Factory = Factory {
+    name: "go",
+    discover,
+};
+
+fn discover(path: &Path, discovery: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    if !path.join("go.mod").try_exists()? {
+        return Ok(Vec::new());
+    }
+
+    let config = package_config::<PackageConfig>(path)?.go;
+
+    if discovery.go_packages {
+        // Falls back to the whole module if `go list` can't tell us its packages.
+        if let Ok(packages) = packages(path) {
+            if !packages.is_empty() {
+                return Ok(packages
+                    .into_iter()
+                    .map(|p| {
+                        Box::new(GoPackageTarget::new(&p, path, config.clone())) as Box<dyn Target>
+                    })
+                    .collect());
+            }
+        }
     }
+
+    Ok(vec![Box::new(GoModTarget::new(path, config))])
 }
 
-pub struct GoModTarget {
-    path: PathBuf,
+#[derive(Deserialize, Default)]
+struct PackageConfig {
+    #[serde(default)]
+    go: GoConfig,
 }
 
-impl GoModTarget {
-    pub fn new(path: &Path) -> Self {
-        Self { path: path.into() }
+/// The `[go]` section of a module's `gentle.toml`. The rest of the environment, like
+/// `CGO_ENABLED` or `GOFLAGS`, is passed through as is.
+#[derive(Deserialize, Default, Clone, Debug, PartialEq, Eq)]
+struct GoConfig {
+    /// Build tags for `go test` and `go build`, e.g. `["integration"]`.
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Other flags for `go test` and `go build`, e.g. `["-race"]`.
+    #[serde(default)]
+    flags: Vec<String>,
+    /// Environment variables to set, e.g. `{ CGO_ENABLED = "1" }`.
+    #[serde(default)]
+    env: HashMap<String, String>,
+}
+
+impl GoConfig {
+    /// `go <subcommand>` for the module at `module`, with the configured tags, flags and
+    /// environment.
+    fn command(&self, subcommand: &str, module: &Path) -> Command {
+        let mut command = Command::new("go");
+        command.arg(subcommand);
+        if !self.tags.is_empty() {
+            command.arg(format!("-tags={}", self.tags.join(",")));
+        }
+        command
+            .args(&self.flags)
+            .env("GOCACHE", absolute(cache_dir(module)))
+            .envs(&self.env);
+        command
     }
+}
+
+/// Directories of every package in the module at `module`.
+fn packages(module: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let out = Command::new("go")
+        .args(["list", "-f", "{{.Dir}}", "./..."])
+        .current_dir(module)
+        .output()?;
+    anyhow::ensure!(
+        out.status.success(),
+        "go list failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
 
-    fn cache_dir(&self) -> PathBuf {
-        std::env::var("GOCACHE")
-            .map(PathBuf::from)
-            .unwrap_or_else(|_| {
-                Path::new(&std::env::var("HOME").unwrap_or(String::from("/")))
-                    .join(".cache/go-build")
+    let root = module.canonicalize()?;
+    String::from_utf8(out.stdout)?
+        .lines()
+        .map(|dir| {
+            let relative = Path::new(dir).canonicalize()?;
+            let relative = relative.strip_prefix(&root)?;
+            Ok(if relative.as_os_str().is_empty() {
+                module.to_path_buf()
+            } else {
+                module.join(relative)
             })
-            .into()
+        })
+        .collect()
+}
+
+/// Checks the documentation of the package at `dir` can be read, which `go doc` can't do for a
+/// package that doesn't parse. `go doc` takes no build flags.
+fn doc(ctx: &TaskContext, config: &GoConfig, dir: &Path) -> anyhow::Result<()> {
+    ctx.run(
+        Command::new("go")
+            .args(["doc", "-all", "."])
+            .envs(&config.env)
+            .current_dir(dir),
+    )
+}
+
+/// Runs `go test` on `packages` in `dir` with a coverage profile, converted to lcov at `out`.
+fn coverage(
+    ctx: &TaskContext,
+    config: &GoConfig,
+    dir: &Path,
+    packages: &str,
+    module: &Path,
+    out: &Path,
+) -> anyhow::Result<()> {
+    // Absolute, since `go test` runs in `dir`.
+    let profile = std::env::current_dir()?.join(out.with_extension("coverprofile"));
+    ctx.run(
+        config
+            .command("test", module)
+            .arg(format!("-coverprofile={}", profile.display()))
+            .arg(packages)
+            .current_dir(dir),
+    )?;
+
+    let lcov = lcov_from_profile(
+        &std::fs::read_to_string(&profile)?,
+        &module_path(module)?,
+        &module.canonicalize()?,
+    )?;
+    let _ = std::fs::remove_file(&profile);
+    std::fs::write(out, lcov.to_string())?;
+    Ok(())
+}
+
+/// The `module` path declared in the `go.mod` of the module at `module`.
+fn module_path(module: &Path) -> anyhow::Result<String> {
+    let go_mod = std::fs::read_to_string(module.join("go.mod"))?;
+    go_mod
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("module "))
+        .map(|path| path.trim().trim_matches('"').to_string())
+        .ok_or_else(|| anyhow::anyhow!("no module path in {}", module.join("go.mod").display()))
+}
+
+/// Converts a `go test -coverprofile` profile to lcov. Each line after the mode is a block,
+/// `{import path}/{file}:{line}.{column},{line}.{column} {statements} {count}`.
+fn lcov_from_profile(profile: &str, module_path: &str, module: &Path) -> anyhow::Result<Lcov> {
+    let mut lcov = Lcov::default();
+    for line in profile.lines() {
+        if line.starts_with("mode:") || line.trim().is_empty() {
+            continue;
+        }
+        let malformed = || anyhow::anyhow!("malformed coverage block: {line:?}");
+
+        let mut fields = line.split_whitespace();
+        let (Some(block), Some(_), Some(count)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(malformed());
+        };
+        let (file, range) = block.rsplit_once(':').ok_or_else(malformed)?;
+        let (start, end) = range.split_once(',').ok_or_else(malformed)?;
+        let line_of = |position: &str| -> anyhow::Result<u32> {
+            Ok(position.split('.').next().unwrap_or_default().parse()?)
+        };
+
+        let file = file.strip_prefix(module_path).unwrap_or(file);
+        let file = module.join(file.trim_start_matches('/'));
+        let count = count.parse()?;
+        for number in line_of(start)?..=line_of(end)? {
+            lcov.hit(&file, number, count);
+        }
+    }
+    Ok(lcov)
+}
+
+/// Where builds of the module at `module` are cached: `$GOCACHE` if it's set, otherwise inside the
+/// module, so a saved cache restores to the same place on a machine with a different `$HOME`.
+fn cache_dir(module: &Path) -> PathBuf {
+    std::env::var("GOCACHE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| module.join(".gocache"))
+}
+
+/// `go` only accepts an absolute `GOCACHE`.
+fn absolute(path: PathBuf) -> PathBuf {
+    match std::env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path,
+    }
+}
+
+pub struct GoModTarget {
+    path: PathBuf,
+    config: GoConfig,
+}
+
+impl GoModTarget {
+    fn new(path: &Path, config: GoConfig) -> Self {
+        Self {
+            path: path.into(),
+            config,
+        }
     }
 }
 
 impl Display for GoModTarget {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let package = self.path.display().to_string().replacen("./", "", 1);
-        write!(f, "//{package}:go_mod")
+        write!(f, "{}", self.address())
+    }
+}
+
+fn fingerprint(module: &Path) -> anyhow::Result<Option<String>> {
+    let mut fingerprint = Fingerprint::new().files(module, &[])?;
+    let go_mod = std::fs::read_to_string(module.join("go.mod"))?;
+    // Modules replaced by a local copy, which builds of this one use instead.
+    for replacement in local_paths(&go_mod, "replace") {
+        fingerprint = fingerprint.files(&module.join(replacement), &[])?;
+    }
+    if let Some(go_work) = go_work(module)? {
+        let dir = go_work.parent().unwrap_or(Path::new("."));
+        let contents = std::fs::read_to_string(&go_work)?;
+        fingerprint = fingerprint
+            .file(&go_work)?
+            .file(&go_work.with_extension("work.sum"))?;
+        let used = local_paths(&contents, "use");
+        for other in used.iter().chain(&local_paths(&contents, "replace")) {
+            fingerprint = fingerprint.files(&dir.join(other), &[])?;
+        }
+    }
+    Ok(Some(fingerprint.tool("go", &["version"])?.finish()))
+}
+
+/// The workspace file `go` builds the module at `module` with, if any.
+fn go_work(module: &Path) -> anyhow::Result<Option<PathBuf>> {
+    match std::env::var("GOWORK") {
+        Ok(gowork) if gowork == "off" => return Ok(None),
+        Ok(gowork) if !gowork.is_empty() => return Ok(Some(gowork.into())),
+        _ => {}
+    }
+    for dir in std::fs::canonicalize(module)?.ancestors() {
+        let go_work = dir.join("go.work");
+        if go_work.try_exists()? {
+            return Ok(Some(go_work));
+        }
+    }
+    Ok(None)
+}
+
+/// The local directories named by `directive` lines of a `go.mod` or `go.work`: the modules
+/// `use`d, or the replacements of `replace`s that are paths rather than module versions.
+fn local_paths(contents: &str, directive: &str) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let mut in_block = false;
+    for line in contents.lines() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        let spec = if in_block {
+            if line == ")" {
+                in_block = false;
+                continue;
+            }
+            line
+        } else {
+            match line.split_once(char::is_whitespace) {
+                Some((word, "(")) if word == directive => {
+                    in_block = true;
+                    continue;
+                }
+                Some((word, spec)) if word == directive => spec,
+                _ => continue,
+            }
+        };
+
+        let path = spec.split_once("=>").map_or(spec, |(_, to)| to);
+        let Some(path) = path.split_whitespace().next() else {
+            continue;
+        };
+        let path = path.trim_matches('"');
+        if path == "." || path.starts_with("./") || path.starts_with("../") || path.starts_with('/')
+        {
+            paths.push(PathBuf::from(path));
+        }
     }
+    paths
 }
 
 impl Target for GoModTarget {
-    fn perform_test(&self) -> anyhow::Result<()> {
-        let out = Command::new("go")
-            .args(&["test"])
-            .env("GOCACHE", self.cache_dir())
-            .current_dir(&self.path)
-            .output()?;
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("go_mod"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(
+            self.config
+                .command("test", &self.path)
+                .current_dir(&self.path),
+        )
+    }
+
+    fn perform_run(&self, args: &[String]) -> anyhow::Result<()> {
+        run_attached(
+            self.config
+                .command("run", &self.path)
+                .arg(".")
+                .args(args)
+                .current_dir(&self.path),
+        )
+    }
+
+    fn perform_build(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(
+            self.config
+                .command("build", &self.path)
+                .arg("./...")
+                .current_dir(&self.path),
+        )
+    }
+
+    /// Go has no separate check, and building is about as quick.
+    fn perform_check(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        self.perform_build(ctx)
+    }
+
+    fn perform_doc(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        for package in packages(&self.path)? {
+            doc(ctx, &self.config, &package)?;
+        }
+        Ok(())
+    }
 
-        out.success_ok()
-            .map(|_| ())
-            .map_err(|out| anyhow::anyhow!(out.stderr))
+    fn perform_coverage(&self, ctx: &TaskContext, out: &Path) -> anyhow::Result<()> {
+        coverage(ctx, &self.config, &self.path, "./...", &self.path, out)
+    }
+
+    fn perform_fmt_check(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        let out = ctx
+            .output(
+                Command::new("gofmt")
+                    .args(["-l", "."])
+                    .current_dir(&self.path),
+            )?
+            .success_ok()
+            .map_err(|out| anyhow::anyhow!(out.stderr))?;
+
+        // gofmt exits successfully even when files need formatting, it only lists them.
+        if out.stdout.trim().is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("files need formatting:\n{}", out.stdout))
+        }
     }
 
     fn cache_paths(&self) -> HashSet<PathBuf> {
-        [self.cache_dir()].into_iter().collect()
+        [cache_dir(&self.path)].into_iter().collect()
+    }
+
+    fn input_fingerprint(&self) -> anyhow::Result<Option<String>> {
+        fingerprint(&self.path)
+    }
+}
+
+/// A single package of a module, so packages can run in parallel.
+pub struct GoPackageTarget {
+    path: PathBuf,
+    module: PathBuf,
+    config: GoConfig,
+}
+
+impl GoPackageTarget {
+    fn new(path: &Path, module: &Path, config: GoConfig) -> Self {
+        Self {
+            path: path.into(),
+            module: module.into(),
+            config,
+        }
+    }
+}
+
+impl Display for GoPackageTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for GoPackageTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("go_pkg"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(
+            self.config
+                .command("test", &self.module)
+                .arg(".")
+                .current_dir(&self.path),
+        )
+    }
+
+    fn perform_run(&self, args: &[String]) -> anyhow::Result<()> {
+        run_attached(
+            self.config
+                .command("run", &self.module)
+                .arg(".")
+                .args(args)
+                .current_dir(&self.path),
+        )
+    }
+
+    fn perform_build(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(
+            self.config
+                .command("build", &self.module)
+                .arg(".")
+                .current_dir(&self.path),
+        )
+    }
+
+    /// Go has no separate check, and building is about as quick.
+    fn perform_check(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        self.perform_build(ctx)
+    }
+
+    fn perform_doc(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        doc(ctx, &self.config, &self.path)
+    }
+
+    fn perform_coverage(&self, ctx: &TaskContext, out: &Path) -> anyhow::Result<()> {
+        coverage(ctx, &self.config, &self.path, ".", &self.module, out)
+    }
+
+    fn perform_fmt_check(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        // Only this package's files, subdirectories are other packages.
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&self.path)? {
+            let name = entry?.file_name();
+            if Path::new(&name).extension() == Some("go".as_ref()) {
+                files.push(name);
+            }
+        }
+
+        let out = ctx
+            .output(
+                Command::new("gofmt")
+                    .arg("-l")
+                    .args(&files)
+                    .current_dir(&self.path),
+            )?
+            .success_ok()
+            .map_err(|out| anyhow::anyhow!(out.stderr))?;
+
+        if out.stdout.trim().is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("files need formatting:\n{}", out.stdout))
+        }
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        [cache_dir(&self.module)].into_iter().collect()
+    }
+
+    fn input_fingerprint(&self) -> anyhow::Result<Option<String>> {
+        // The whole module, since a package is affected by the other packages it imports.
+        fingerprint(&self.module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_paths_are_replacements_and_used_modules() {
+        let go_mod = "module example.com/a\n\nreplace example.com/b => ../b\n\n\
+                      replace (\n\texample.com/c v1.0.0 => ./c // vendored\n\
+                      \texample.com/d => example.com/e v1.2.0\n)\n";
+        assert_eq!(
+            local_paths(go_mod, "replace"),
+            [PathBuf::from("../b"), PathBuf::from("./c")]
+        );
+
+        let go_work = "go 1.21\n\nuse (\n\t.\n\t./tools\n)\n";
+        assert_eq!(
+            local_paths(go_work, "use"),
+            [PathBuf::from("."), PathBuf::from("./tools")]
+        );
+    }
+
+    #[test]
+    fn go_packages_falls_back_to_module_without_packages() {
+        let (dir, _) = discover_files(discover, &[("go.mod", "module example.com/foo\n")]);
+
+        let discovery = Discovery {
+            go_packages: true,
+            ..Default::default()
+        };
+        let targets = discover(dir.path(), &discovery).unwrap();
+
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].address().identifier, "go_mod");
+    }
+
+    #[test]
+    fn config_adds_tags_flags_and_env() {
+        let (dir, _) = discover_files(
+            discover,
+            &[(
+                "gentle.toml",
+                "[go]\ntags = [\"integration\", \"slow\"]\nflags = [\"-race\"]\nenv = { CGO_ENABLED = \"1\" }\n",
+            )],
+        );
+
+        let config = package_config::<PackageConfig>(dir.path()).unwrap().go;
+        let command = config.command("test", dir.path());
+
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["test", "-tags=integration,slow", "-race"]
+        );
+        assert!(command
+            .get_envs()
+            .any(|(k, v)| k == "CGO_ENABLED" && v == Some("1".as_ref())));
+    }
+
+    #[test]
+    fn cache_is_inside_the_module_unless_gocache_is_set() {
+        let target = GoModTarget::new(Path::new("./svc"), GoConfig::default());
+
+        let expected = match std::env::var("GOCACHE") {
+            Ok(cache) => PathBuf::from(cache),
+            Err(_) => PathBuf::from("./svc/.gocache"),
+        };
+        assert_eq!(target.cache_paths(), [expected].into());
+    }
+
+    #[test]
+    fn coverage_profile_becomes_lcov_of_module_files() {
+        let profile = "mode: set\n\
+                       example.com/foo/bar/bar.go:3.14,5.2 1 1\n\
+                       example.com/foo/bar/bar.go:7.20,8.2 1 0\n";
+
+        let lcov = lcov_from_profile(profile, "example.com/foo", Path::new("/src/foo")).unwrap();
+
+        assert_eq!(
+            lcov.to_string(),
+            "SF:/src/foo/bar/bar.go\nDA:3,1\nDA:4,1\nDA:5,1\nDA:7,0\nDA:8,0\nLF:5\nLH:3\n\
+             end_of_record\n"
+        );
+    }
+
+    #[test]
+    fn missing_config_is_empty() {
+        let (dir, _) = discover_files(discover, &[]);
+
+        assert_eq!(
+            package_config::<PackageConfig>(dir.path()).unwrap().go,
+            GoConfig::default()
+        );
     }
 }
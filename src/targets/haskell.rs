@@ -0,0 +1,158 @@
+use super::*;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "haskell",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    let stack = path.join("stack.yaml").try_exists()?;
+    let cabal_files = files_with_extension(path, "cabal")?;
+    if !stack && cabal_files.is_empty() {
+        return Ok(Vec::new());
+    }
+    // Packages of a Stack project are tested by the project's target.
+    if !stack && in_stack_project(path)? {
+        return Ok(Vec::new());
+    }
+
+    // Stack projects without a `.cabal` of their own, like those with only `package.yaml` or
+    // with their packages in subdirectories, can't be told apart from ones without tests.
+    let mut tested = cabal_files.is_empty();
+    for cabal in cabal_files {
+        tested |= has_test_suite(&std::fs::read_to_string(cabal)?);
+    }
+    if !tested {
+        return Ok(Vec::new());
+    }
+
+    let tool = if stack { Tool::Stack } else { Tool::Cabal };
+    Ok(vec![Box::new(HaskellTarget::new(path, tool))])
+}
+
+/// Whether the `.cabal` file `cabal` declares a `test-suite` stanza. Stanzas start unindented,
+/// and their names aren't case sensitive.
+fn has_test_suite(cabal: &str) -> bool {
+    cabal.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.strip_prefix("test-suite")
+            .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+    })
+}
+
+/// Whether an ancestor of `path` has a `stack.yaml`.
+fn in_stack_project(path: &Path) -> anyhow::Result<bool> {
+    for ancestor in path.ancestors().skip(1) {
+        if ancestor.join("stack.yaml").try_exists()? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Tool {
+    Stack,
+    Cabal,
+}
+
+pub struct HaskellTarget {
+    path: PathBuf,
+    tool: Tool,
+}
+
+impl HaskellTarget {
+    fn new(path: &Path, tool: Tool) -> Self {
+        Self {
+            path: path.into(),
+            tool,
+        }
+    }
+
+    fn command(&self, subcommand: &str) -> Command {
+        let program = match self.tool {
+            Tool::Stack => "stack",
+            Tool::Cabal => "cabal",
+        };
+        let mut command = Command::new(program);
+        command.arg(subcommand).current_dir(&self.path);
+        command
+    }
+}
+
+impl Display for HaskellTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for HaskellTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("haskell"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(&mut self.command("test"))
+    }
+
+    fn perform_build(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(&mut self.command("build"))
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        let dir = match self.tool {
+            Tool::Stack => ".stack-work",
+            Tool::Cabal => "dist-newstyle",
+        };
+        [self.path.join(dir)].into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TESTED: &str = "cabal-version: 2.4\nname: foo\n\nlibrary\n  exposed-modules: Foo\n\n\
+                          Test-Suite spec\n  type: exitcode-stdio-1.0\n  main-is: Spec.hs\n";
+
+    #[test]
+    fn package_with_a_test_suite_is_a_target() {
+        let (_dir, targets) = discover_files(discover, &[("foo.cabal", TESTED)]);
+
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].address().identifier, "haskell");
+        assert!(targets[0]
+            .cache_paths()
+            .iter()
+            .all(|path| path.ends_with("dist-newstyle")));
+    }
+
+    #[test]
+    fn stack_is_preferred() {
+        let (_dir, targets) =
+            discover_files(discover, &[("foo.cabal", TESTED), ("stack.yaml", "")]);
+
+        assert!(targets[0]
+            .cache_paths()
+            .iter()
+            .all(|path| path.ends_with(".stack-work")));
+    }
+
+    #[test]
+    fn package_without_a_test_suite_is_skipped() {
+        assert!(
+            discover_files(discover, &[("foo.cabal", "name: foo\n\nlibrary\n")])
+                .1
+                .is_empty()
+        );
+        assert!(!has_test_suite("  -- test-suite spec\n"));
+    }
+}
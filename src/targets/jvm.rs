@@ -0,0 +1,257 @@
+use super::*;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "jvm",
+    discover,
+};
+
+const GRADLE_BUILD_FILES: &[&str] = &["build.gradle", "build.gradle.kts"];
+const GRADLE_SETTINGS_FILES: &[&str] = &["settings.gradle", "settings.gradle.kts"];
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    let tool = if path.join("pom.xml").try_exists()? {
+        // Modules of a multi-module build are tested by the build's target.
+        if in_maven_build(path)? {
+            return Ok(Vec::new());
+        }
+        BuildTool::Maven
+    } else if any_exists(path, GRADLE_SETTINGS_FILES)? {
+        BuildTool::Gradle {
+            wrapper: gradle_wrapper(path)?,
+        }
+    } else if any_exists(path, GRADLE_BUILD_FILES)? {
+        // As are projects of a multi-project build, which Gradle runs from its settings.
+        if in_gradle_build(path)? {
+            return Ok(Vec::new());
+        }
+        BuildTool::Gradle {
+            wrapper: gradle_wrapper(path)?,
+        }
+    } else {
+        return Ok(Vec::new());
+    };
+
+    Ok(vec![Box::new(JvmTarget::new(path, tool))])
+}
+
+/// Whether the `pom.xml` of an ancestor of `path` lists it as a module.
+fn in_maven_build(path: &Path) -> anyhow::Result<bool> {
+    for ancestor in path.ancestors().skip(1) {
+        let pom = ancestor.join("pom.xml");
+        if !pom.try_exists()? {
+            continue;
+        }
+
+        let relative = path.strip_prefix(ancestor)?;
+        if maven_modules(&std::fs::read_to_string(pom)?)
+            .iter()
+            .any(|module| module == relative)
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The directories of the `<module>`s a `pom.xml` aggregates, relative to it.
+fn maven_modules(pom: &str) -> Vec<PathBuf> {
+    pom.split("<module>")
+        .skip(1)
+        .filter_map(|rest| rest.split_once("</module>"))
+        .map(|(module, _)| {
+            Path::new(module.trim())
+                .components()
+                .filter(|c| *c != Component::CurDir)
+                .collect()
+        })
+        .collect()
+}
+
+/// Whether an ancestor of `path` has Gradle settings.
+fn in_gradle_build(path: &Path) -> anyhow::Result<bool> {
+    for ancestor in path.ancestors().skip(1) {
+        if any_exists(ancestor, GRADLE_SETTINGS_FILES)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The `gradlew` of the build containing `path`, in it or an ancestor up to the one with the
+/// build's settings.
+fn gradle_wrapper(path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    for ancestor in path.ancestors() {
+        let wrapper = ancestor.join(GRADLE_WRAPPER);
+        if wrapper.try_exists()? {
+            return Ok(Some(wrapper));
+        }
+        if any_exists(ancestor, GRADLE_SETTINGS_FILES)? {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+const GRADLE_WRAPPER: &str = if cfg!(windows) {
+    "gradlew.bat"
+} else {
+    "gradlew"
+};
+
+#[derive(Debug, PartialEq, Eq)]
+enum BuildTool {
+    Maven,
+    /// The build's `gradlew` to use instead of the installed `gradle`, if it has one.
+    Gradle {
+        wrapper: Option<PathBuf>,
+    },
+}
+
+pub struct JvmTarget {
+    path: PathBuf,
+    tool: BuildTool,
+}
+
+impl JvmTarget {
+    fn new(path: &Path, tool: BuildTool) -> Self {
+        Self {
+            path: path.into(),
+            tool,
+        }
+    }
+
+    fn command(&self, task: &str) -> anyhow::Result<Command> {
+        let mut command = match &self.tool {
+            BuildTool::Maven => Command::new("mvn"),
+            // The wrapper's path must be absolute, a relative one isn't resolved against
+            // `current_dir` everywhere.
+            BuildTool::Gradle {
+                wrapper: Some(wrapper),
+            } => Command::new(std::fs::canonicalize(wrapper)?),
+            BuildTool::Gradle { wrapper: None } => Command::new("gradle"),
+        };
+        command.arg(task).current_dir(&self.path);
+        Ok(command)
+    }
+}
+
+impl Display for JvmTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for JvmTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from(match self.tool {
+                BuildTool::Maven => "maven",
+                BuildTool::Gradle { .. } => "gradle",
+            }),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(&mut self.command("test")?)
+    }
+
+    fn perform_build(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        match self.tool {
+            BuildTool::Maven => ctx.run(&mut self.command("compile")?),
+            BuildTool::Gradle { .. } => ctx.run(&mut self.command("assemble")?),
+        }
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        match self.tool {
+            BuildTool::Maven => [self.path.join("target")].into_iter().collect(),
+            BuildTool::Gradle { .. } => [self.path.join("build"), self.path.join(".gradle")]
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identifiers(files: &[(&str, &str)]) -> Vec<String> {
+        let (_dir, targets) = discover_files(discover, files);
+        targets.iter().map(|t| t.address().identifier).collect()
+    }
+
+    #[test]
+    fn detects_maven_and_gradle() {
+        assert_eq!(identifiers(&[("pom.xml", "")]), ["maven"]);
+        assert_eq!(identifiers(&[("build.gradle.kts", "")]), ["gradle"]);
+        assert_eq!(identifiers(&[("settings.gradle", "")]), ["gradle"]);
+        assert!(identifiers(&[("gradlew", "")]).is_empty());
+    }
+
+    #[test]
+    fn multi_module_builds_are_one_target() {
+        let pom =
+            "<project>\n  <modules>\n    <module>core</module>\n    <module>./app</module>\n  \
+                   </modules>\n</project>\n";
+        let (dir, _) = discover_files(
+            discover,
+            &[
+                ("pom.xml", pom),
+                ("core/pom.xml", ""),
+                ("app/pom.xml", ""),
+                ("tools/pom.xml", ""),
+            ],
+        );
+        let found = |module: &str| discover(&dir.path().join(module), &Discovery::default());
+
+        assert!(found("core").unwrap().is_empty());
+        assert!(found("app").unwrap().is_empty());
+        // Not a module of the build, so not tested by it.
+        assert_eq!(found("tools").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn multi_project_builds_are_one_target() {
+        let (dir, targets) = discover_files(
+            discover,
+            &[
+                ("settings.gradle.kts", "include(\"core\")\n"),
+                ("core/build.gradle.kts", ""),
+            ],
+        );
+
+        assert_eq!(targets.len(), 1);
+        assert!(discover(&dir.path().join("core"), &Discovery::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn gradle_prefers_the_wrapper() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, _) = discover_files(
+            discover,
+            &[
+                (GRADLE_WRAPPER, "#!/bin/sh\necho \"$@\" > ran\n"),
+                ("app/build.gradle", ""),
+            ],
+        );
+        let wrapper = dir.path().join(GRADLE_WRAPPER);
+        std::fs::set_permissions(&wrapper, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let app = dir.path().join("app");
+        let targets = discover(&app, &Discovery::default()).unwrap();
+        targets[0].perform_test(&TaskContext::default()).unwrap();
+
+        assert_eq!(std::fs::read_to_string(app.join("ran")).unwrap(), "test\n");
+    }
+}
@@ -0,0 +1,117 @@
+use super::*;
+
+const MAKEFILES: &[&str] = &["GNUmakefile", "makefile", "Makefile"];
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "make",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    // A Makefile without a test rule isn't something to test. Where another factory finds
+    // targets too, `Discovery::preferred` drops this one.
+    if !has_test_rule(path)? {
+        return Ok(Vec::new());
+    }
+
+    Ok(vec![Box::new(MakeTarget::new(path))])
+}
+
+/// Whether the makefile in `dir` that make would read has a `test` rule.
+///
+/// The makefile is read rather than asking `make -n test` or `make -q test`, which both still run
+/// recipe lines starting with `+` or using `$(MAKE)`, as well as any `$(shell ...)`, for every
+/// Makefile discovery finds. The cost is missing `test` rules that only come from included or
+/// generated makefiles, or from pattern rules.
+fn has_test_rule(dir: &Path) -> anyhow::Result<bool> {
+    for name in MAKEFILES {
+        match std::fs::read_to_string(dir.join(name)) {
+            Ok(makefile) => return Ok(defines_rule(&makefile, "test")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(false)
+}
+
+/// Whether `makefile` has a rule line, like `check test: build`, with `target` among its targets.
+fn defines_rule(makefile: &str, target: &str) -> bool {
+    makefile
+        .lines()
+        // Recipe lines.
+        .filter(|line| !line.starts_with('\t'))
+        .filter_map(|line| line.split('#').next()?.split_once(':'))
+        // Not variables, like `A = b:c`, `A := b` or `A ::= b`.
+        .filter(|(targets, rest)| {
+            !targets.contains('=') && !rest.trim_start_matches(':').starts_with('=')
+        })
+        .any(|(targets, _)| targets.split_whitespace().any(|t| t == target))
+}
+
+pub struct MakeTarget {
+    path: PathBuf,
+}
+
+impl MakeTarget {
+    fn new(path: &Path) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Display for MakeTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for MakeTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("make"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(Command::new("make").arg("test").current_dir(&self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discover_makefile(makefile: &str) -> Vec<Box<dyn Target>> {
+        discover_files(discover, &[("Makefile", makefile)]).1
+    }
+
+    #[test]
+    fn makefile_with_test_rule_is_a_target() {
+        let targets = discover_makefile("test:\n\ttrue\n");
+
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].address().identifier, "make");
+    }
+
+    #[test]
+    fn makefile_without_test_rule_is_skipped() {
+        assert!(discover_makefile("build:\n\ttrue\n").is_empty());
+        assert!(discover_makefile("test := unit\n\nbuild:\n\ttrue\n").is_empty());
+    }
+
+    #[test]
+    fn discovery_runs_nothing() {
+        let (dir, targets) = discover_files(
+            discover,
+            &[("Makefile", ".PHONY: test\nlint test: build\n\t+touch ran\n")],
+        );
+
+        assert_eq!(targets.len(), 1);
+        assert!(!dir.path().join("ran").exists());
+    }
+}
@@ -1,41 +1,532 @@
-use std::{collections::*, fmt::Display, path::*, process::*};
+use std::{
+    collections::*,
+    ffi::OsString,
+    fmt::Display,
+    io::{Read, Write},
+    path::*,
+    process::*,
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
+use crate::target::{TargetAddress, TargetMatcher};
+
+mod bazel;
+mod dart;
+mod dotnet;
+mod elixir;
 mod go;
+mod haskell;
+mod jvm;
+mod make;
+mod node;
+mod php;
+mod python;
+mod ruby;
 mod rust;
+mod swift;
+
+/// Factories that only keep their targets when no other factory found any in the same directory,
+/// unless `Discovery::precedence` names them.
+const FALLBACKS: &[&str] = &["make"];
+
+/// Settings for finding targets in the working directory.
+#[derive(Clone, Default)]
+pub struct Discovery {
+    /// Also look in gitignored and hidden directories.
+    pub no_ignore: bool,
+    /// Make a target per Go package rather than per module.
+    pub go_packages: bool,
+    /// Names of factories, most preferred first. When several find targets in the same
+    /// directory, only the most preferred one's are kept. Unlisted factories come last.
+    pub precedence: Vec<String>,
+    /// Keep every factory's targets even when `precedence` is set.
+    pub multiple: bool,
+}
+
+impl Discovery {
+    /// The targets to keep of those each factory found in one directory.
+    fn preferred<T>(&self, mut found: Vec<(&str, Vec<T>)>) -> Vec<T> {
+        if self.multiple {
+            return found.into_iter().flat_map(|(_, targets)| targets).collect();
+        }
+
+        let rank = |name: &str| {
+            let position = self.precedence.iter().position(|p| p == name);
+            position.unwrap_or(self.precedence.len())
+        };
+        let best = found.iter().map(|(name, _)| rank(name)).min();
+        found.retain(|(name, _)| Some(rank(name)) == best);
+
+        let defers =
+            |name: &str| FALLBACKS.contains(&name) && !self.precedence.iter().any(|p| p == name);
+        if found.iter().any(|(name, _)| !defers(name)) {
+            found.retain(|(name, _)| !defers(name));
+        }
+        found.into_iter().flat_map(|(_, targets)| targets).collect()
+    }
+}
+
+/// Gitignore-style file of paths for discovery to skip, even if git tracks them.
+const IGNORE_FILE: &str = ".gentleignore";
+
+pub fn targets(discovery: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    for name in &discovery.precedence {
+        anyhow::ensure!(
+            TARGET_DISCOVERY.iter().any(|factory| factory.name == name),
+            "unknown factory in discovery precedence: {name:?}"
+        );
+    }
 
-pub fn targets() -> anyhow::Result<Vec<Box<dyn Target>>> {
     let mut result = Vec::new();
 
-    for entry in ignore::Walk::new("./") {
+    for entry in walk(Path::new("./"), discovery) {
         let entry = entry?;
 
         let is_dir = entry.file_type().expect("no stdin/stdout").is_dir();
         if !is_dir {
             continue;
         }
-        let path = entry.into_path();
+        result.extend(discover_dir(&entry.into_path(), discovery)?);
+    }
 
-        for factory in TARGET_DISCOVERY {
-            result.extend(factory(&path)?);
+    Ok(result)
+}
+
+/// The targets every factory finds in `path`, less those `Discovery::preferred` drops.
+fn discover_dir(path: &Path, discovery: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    let mut found = Vec::new();
+    for factory in TARGET_DISCOVERY {
+        let targets = (factory.discover)(path, discovery)?;
+        if !targets.is_empty() {
+            found.push((factory.name, targets));
         }
     }
+    Ok(discovery.preferred(found))
+}
 
-    Ok(result)
+/// Everything under `root` that discovery looks at.
+fn walk(root: &Path, discovery: &Discovery) -> ignore::Walk {
+    ignore::WalkBuilder::new(root)
+        .git_ignore(!discovery.no_ignore)
+        .hidden(!discovery.no_ignore)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .build()
+}
+
+/// Finds one ecosystem's targets in a directory.
+pub struct Factory {
+    /// What `Discovery::precedence` calls this, e.g. `rust`.
+    pub name: &'static str,
+    pub discover: Discover,
 }
 
+pub type Discover = fn(&Path, &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>>;
+
 #[linkme::distributed_slice]
-static TARGET_DISCOVERY: [fn(&Path) -> anyhow::Result<Vec<Box<dyn Target>>>] = [..];
+static TARGET_DISCOVERY: [Factory] = [..];
 
 pub trait Target: Display + Send + Sync + 'static {
-    fn perform_test(&self) -> anyhow::Result<()>;
+    fn address(&self) -> TargetAddress;
+
+    /// The directory this target was discovered in.
+    fn path(&self) -> &Path;
+
+    /// What sort of target this is, like `rust_crate`: its identifier without any variant.
+    fn kind(&self) -> String {
+        let identifier = self.address().identifier;
+        match identifier.split_once('[') {
+            Some((kind, _)) => kind.to_string(),
+            None => identifier,
+        }
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()>;
+
+    fn perform_build(&self, _: &TaskContext) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn perform_fmt_check(&self, _: &TaskContext) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Checks that the target compiles, as quickly as the tool can tell, without building it.
+    fn perform_check(&self, _: &TaskContext) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Builds documentation, failing on warnings where the tool can tell.
+    fn perform_doc(&self, _: &TaskContext) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Runs the tests measuring line coverage, writing an lcov report to `out`. Targets without a
+    /// coverage tool write nothing.
+    fn perform_coverage(&self, _: &TaskContext, _out: &Path) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Builds and runs this target's program with `args`, attached to the terminal.
+    fn perform_run(&self, _args: &[String]) -> anyhow::Result<()> {
+        anyhow::bail!("{} can't be run", self.address())
+    }
 
     fn cache_paths(&self) -> HashSet<PathBuf> {
         Default::default()
     }
+
+    /// Globs of files under `cache_paths` that aren't worth saving, like machine-specific
+    /// scratch space, e.g. `**/incremental/**`.
+    fn cache_excludes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// A hash of everything that affects this target's tasks, if it can tell. Tasks that passed
+    /// are skipped while this is unchanged.
+    fn input_fingerprint(&self) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Targets whose tasks must pass before this target's task for the same action starts.
+    fn depends_on(&self) -> Vec<TargetMatcher> {
+        Vec::new()
+    }
+
+    /// Roughly how much of the machine this target's tasks use, as a number of typical targets.
+    /// Tasks run at once never weigh more than `--load` in total.
+    fn weight(&self) -> u32 {
+        1
+    }
+}
+
+/// Whether any of `names` exists in `dir`.
+fn any_exists(dir: &Path, names: &[&str]) -> anyhow::Result<bool> {
+    for name in names {
+        if dir.join(name).try_exists()? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Files directly in `dir` ending in `.{extension}`, sorted.
+fn files_with_extension(dir: &Path, extension: &str) -> anyhow::Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    files.retain(|file| file.extension().is_some_and(|e| e == extension) && file.is_file());
+    files.sort();
+    Ok(files)
+}
+
+/// File beside a target's manifest with settings for just that target.
+const PACKAGE_CONFIG: &str = "gentle.toml";
+
+/// The package config in `dir`, or the default if it has none.
+fn package_config<T: serde::de::DeserializeOwned + Default>(dir: &Path) -> anyhow::Result<T> {
+    let path = dir.join(PACKAGE_CONFIG);
+    match std::fs::read(&path) {
+        Ok(bytes) => {
+            toml::from_slice(&bytes).map_err(|e| anyhow::anyhow!("parsing {}: {e}", path.display()))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Settings for running a single task's subprocesses.
+#[derive(Clone, Default)]
+pub struct TaskContext {
+    pub timeout: Option<Duration>,
+    /// File every subprocess's output is appended to as it's produced.
+    pub log: Option<PathBuf>,
+    /// Buffer every subprocess's output is appended to, to print in one piece once the task
+    /// finishes.
+    pub buffer: Option<Arc<Mutex<Vec<u8>>>>,
+    /// Whether subprocesses should color their output.
+    pub color: bool,
+    /// Extra environment variables for every subprocess.
+    pub env: BTreeMap<String, String>,
+    /// How many jobs a tool that runs its own in parallel should use, to share the machine with
+    /// the other tasks running at the same time.
+    pub jobs: Option<usize>,
+}
+
+impl TaskContext {
+    /// Like `Command::output`, but kills the child if it runs longer than the timeout.
+    fn output(&self, command: &mut Command) -> anyhow::Result<Output> {
+        if !self.color {
+            // Tools without a color flag of their own often still honor this.
+            command.env("NO_COLOR", "1");
+        }
+        command.envs(&self.env);
+        // In its own process group, so killing it also kills whatever it started, like test
+        // binaries or build scripts.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(command, 0);
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let _group = ProcessGroup::register(&child);
+
+        let mut copies: Vec<Arc<Mutex<dyn Write + Send>>> = Vec::new();
+        if let Some(path) = &self.log {
+            copies.push(Arc::new(Mutex::new(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?,
+            )));
+        }
+        if let Some(buffer) = &self.buffer {
+            copies.push(buffer.clone());
+        }
+
+        // Drain both pipes concurrently so a chatty child can't block on a full pipe.
+        let stdout = read_in_background(child.stdout.take(), copies.clone(), MAX_CAPTURED);
+        let stderr = read_in_background(child.stderr.take(), copies, MAX_CAPTURED);
+
+        let deadline = self.timeout.map(|t| Instant::now() + t);
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+
+            if deadline.is_some_and(|d| d <= Instant::now()) {
+                kill_tree(&mut child)?;
+                child.wait()?;
+                // What it printed before it was killed shows where it hung.
+                let stdout = stdout.join().unwrap_or_default();
+                let stderr = stderr.join().unwrap_or_default();
+                anyhow::bail!(
+                    "killed after {}\n{}\n{}",
+                    humantime::format_duration(self.timeout.unwrap()),
+                    String::from_utf8_lossy(&stderr),
+                    String::from_utf8_lossy(&stdout)
+                );
+            }
+
+            sleep(Duration::from_millis(10));
+        };
+
+        Ok(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+
+    /// Runs `command` with `output`, failing with its output if it doesn't succeed.
+    fn run(&self, command: &mut Command) -> anyhow::Result<()> {
+        self.output(command)?.succeeded()
+    }
+}
+
+/// Process groups of the children running now, to kill if gentle is interrupted.
+static RUNNING: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// Keeps a child in `RUNNING` until dropped.
+struct ProcessGroup(u32);
+
+impl ProcessGroup {
+    fn register(child: &Child) -> Self {
+        RUNNING.lock().unwrap().insert(child.id());
+        ProcessGroup(child.id())
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        RUNNING.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// Kills `child` and, on Unix, everything else in its process group.
+fn kill_tree(child: &mut Child) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        kill_group(child.id());
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    child.kill()
+}
+
+#[cfg(unix)]
+fn kill_group(id: u32) {
+    // A negative pid signals the whole group. It may already be gone, which is fine.
+    unsafe { libc::kill(-(id as libc::pid_t), libc::SIGKILL) };
+}
+
+/// Kills every running child and what it started. Children no longer share gentle's process
+/// group, so an interrupt from the terminal doesn't reach them on its own.
+pub fn kill_running() {
+    let running = std::mem::take(&mut *RUNNING.lock().unwrap());
+    #[cfg(unix)]
+    running.into_iter().for_each(kill_group);
+    #[cfg(not(unix))]
+    drop(running);
+}
+
+/// Runs `command` attached to gentle's own stdin, stdout and stderr.
+fn run_attached(command: &mut Command) -> anyhow::Result<()> {
+    let status = command.status()?;
+    anyhow::ensure!(status.success(), "exited with {status}");
+    Ok(())
+}
+
+/// Bytes of each of a subprocess's stdout and stderr kept in memory, so a task printing without
+/// end can't run gentle out of memory. The log still gets everything.
+const MAX_CAPTURED: usize = 1 << 20;
+
+const TRUNCATED: &[u8] = b"... truncated ...\n";
+
+/// Reads `pipe` to the end, keeping the last `limit` bytes and writing all of them to `copies`.
+fn read_in_background(
+    pipe: Option<impl Read + Send + 'static>,
+    copies: Vec<Arc<Mutex<dyn Write + Send>>>,
+    limit: usize,
+) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let Some(mut pipe) = pipe else {
+            return buf;
+        };
+
+        let mut truncated = false;
+        let mut chunk = [0; 8192];
+        while let Ok(n @ 1..) = pipe.read(&mut chunk) {
+            buf.extend_from_slice(&chunk[..n]);
+            for copy in &copies {
+                let _ = copy.lock().unwrap().write_all(&chunk[..n]);
+            }
+            // Only once it's twice the limit, so each byte is moved at most once.
+            if buf.len() > 2 * limit {
+                buf.drain(..buf.len() - limit);
+                truncated = true;
+            }
+        }
+
+        if buf.len() > limit {
+            buf.drain(..buf.len() - limit);
+            truncated = true;
+        }
+        if truncated {
+            buf.splice(0..0, TRUNCATED.iter().copied());
+        }
+        buf
+    })
+}
+
+fn package(path: &Path) -> String {
+    path.display().to_string().replacen("./", "", 1)
+}
+
+/// Everything under `root` that isn't ignored, except in directories named `exclude`, sorted by
+/// file name.
+fn walk_sources(root: &Path, exclude: &[&str]) -> ignore::Walk {
+    let exclude = exclude.iter().map(OsString::from).collect::<Vec<_>>();
+    ignore::WalkBuilder::new(root)
+        .filter_entry(move |e| !(e.path().is_dir() && exclude.iter().any(|x| x == e.file_name())))
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+}
+
+/// Total size of the files under `root` that aren't ignored, except in directories named
+/// `exclude`, in units of `unit` bytes and at least 1.
+fn source_weight(root: &Path, exclude: &[&str], unit: u64) -> u32 {
+    let bytes = walk_sources(root, exclude)
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum::<u64>();
+    u32::try_from(bytes / unit).unwrap_or(u32::MAX).max(1)
+}
+
+/// Builds a `Target::input_fingerprint`.
+struct Fingerprint(blake3::Hasher);
+
+impl Fingerprint {
+    fn new() -> Self {
+        Fingerprint(blake3::Hasher::new())
+    }
+
+    /// Adds every file under `root` that isn't ignored, except in directories named `exclude`.
+    fn files(mut self, root: &Path, exclude: &[&str]) -> anyhow::Result<Self> {
+        for entry in walk_sources(root, exclude) {
+            let entry = entry?;
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+
+            let path = entry.path();
+            self.0
+                .update(path.strip_prefix(root)?.to_string_lossy().as_bytes());
+            self.0.update(&[0]);
+            let contents = std::fs::read(path)?;
+            self.0.update(&(contents.len() as u64).to_le_bytes());
+            self.0.update(&contents);
+        }
+
+        Ok(self)
+    }
+
+    /// Adds the file at `path`, or that there isn't one.
+    fn file(mut self, path: &Path) -> anyhow::Result<Self> {
+        self.0.update(path.to_string_lossy().as_bytes());
+        self.0.update(&[0]);
+        match std::fs::read(path) {
+            Ok(contents) => {
+                self.0.update(&[1]);
+                self.0.update(&(contents.len() as u64).to_le_bytes());
+                self.0.update(&contents);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.0.update(&[0]);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(self)
+    }
+
+    /// Adds the output of a command, like a compiler's version.
+    fn tool(mut self, program: &str, args: &[&str]) -> anyhow::Result<Self> {
+        let out = Command::new(program)
+            .args(args)
+            .output()?
+            .success_ok()
+            .map_err(|out| anyhow::anyhow!(out.stderr))?;
+        self.0.update(out.stdout.as_bytes());
+        Ok(self)
+    }
+
+    fn finish(self) -> String {
+        self.0.finalize().to_hex().to_string()
+    }
+}
+
+fn on_path(program: &str) -> bool {
+    let Some(paths) = std::env::var_os("PATH") else {
+        return false;
+    };
+
+    std::env::split_paths(&paths).any(|dir| dir.join(program).is_file())
 }
 
 trait OutputExt {
     fn success_ok(self) -> Result<StringOutput, StringOutput>;
+
+    /// Fails with the stderr and stdout of a command that didn't succeed.
+    fn succeeded(self) -> anyhow::Result<()>;
 }
 
 impl OutputExt for Output {
@@ -50,9 +541,295 @@ impl OutputExt for Output {
             Err(output)
         }
     }
+
+    fn succeeded(self) -> anyhow::Result<()> {
+        self.success_ok()
+            .map(|_| ())
+            .map_err(|out| anyhow::anyhow!("{}\n{}", out.stderr, out.stdout))
+    }
 }
 
 struct StringOutput {
     stdout: String,
     stderr: String,
 }
+
+/// Writes `files`, as paths and their contents, into a new directory and runs `discover` on it.
+/// The directory lives as long as the returned `TempDir`.
+#[cfg(test)]
+fn discover_files(
+    discover: Discover,
+    files: &[(&str, &str)],
+) -> (tempfile::TempDir, Vec<Box<dyn Target>>) {
+    let dir = tempfile::tempdir().unwrap();
+    for (file, contents) in files {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    let targets = discover(dir.path(), &Discovery::default()).unwrap();
+    (dir, targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence_keeps_the_most_preferred_factory() {
+        let found = || vec![("make", vec![1]), ("rust", vec![2, 3]), ("node", vec![4])];
+        let discovery = |precedence: &[&str], multiple| Discovery {
+            precedence: precedence.iter().map(|p| p.to_string()).collect(),
+            multiple,
+            ..Default::default()
+        };
+
+        assert_eq!(discovery(&[], false).preferred(found()), [2, 3, 4]);
+        assert_eq!(
+            discovery(&["rust", "make"], false).preferred(found()),
+            [2, 3]
+        );
+        assert_eq!(
+            discovery(&["rust", "make"], true).preferred(found()),
+            [1, 2, 3, 4]
+        );
+        // Unlisted factories tie for last.
+        assert_eq!(discovery(&["go"], false).preferred(found()), [2, 3, 4]);
+    }
+
+    #[test]
+    fn fallbacks_defer_to_other_factories() {
+        let discovery = |precedence: &[&str]| Discovery {
+            precedence: precedence.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            discovery(&[]).preferred(vec![("make", vec![1]), ("dotnet", vec![2])]),
+            [2]
+        );
+        assert_eq!(discovery(&[]).preferred(vec![("make", vec![1])]), [1]);
+        // Unless asked for by name.
+        assert_eq!(
+            discovery(&["make"]).preferred(vec![("make", vec![1]), ("dotnet", vec![2])]),
+            [1]
+        );
+    }
+
+    #[test]
+    fn makefile_defers_only_to_targets_found_beside_it() {
+        let makefile = ("Makefile", "test:\n\ttrue\n");
+        let kinds = |files: &[(&str, &str)]| {
+            let (_dir, targets) = discover_files(discover_dir, files);
+            targets.iter().map(|t| t.kind()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            kinds(&[
+                makefile,
+                ("package.json", r#"{"scripts": {"test": "jest"}}"#)
+            ]),
+            ["node"]
+        );
+        // Manifests without tests leave the Makefile's.
+        assert_eq!(kinds(&[makefile, ("package.json", "{}")]), ["make"]);
+        assert_eq!(kinds(&[makefile, ("Gemfile", "")]), ["make"]);
+        assert_eq!(kinds(&[makefile, ("BUILD", "")]), ["make"]);
+    }
+
+    #[test]
+    fn precedence_and_multiple_keep_a_makefile_beside_a_crate() {
+        let (dir, _) = discover_files(
+            discover_dir,
+            &[
+                ("Makefile", "test:\n\ttrue\n"),
+                ("Cargo.toml", "[package]\nname = \"foo\"\n"),
+            ],
+        );
+        let kinds = |precedence: &[&str], multiple| {
+            let discovery = Discovery {
+                precedence: precedence.iter().map(|p| p.to_string()).collect(),
+                multiple,
+                ..Default::default()
+            };
+            let targets = discover_dir(dir.path(), &discovery).unwrap();
+            targets.iter().map(|t| t.kind()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(kinds(&[], false), ["rust_crate"]);
+        assert_eq!(kinds(&["make", "rust"], false), ["make"]);
+        let mut both = kinds(&[], true);
+        both.sort();
+        assert_eq!(both, ["make", "rust_crate"]);
+    }
+
+    #[test]
+    fn gentleignore_skips_directories_at_any_level() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["fixtures/data", "a/experiment", "a/kept"] {
+            std::fs::create_dir_all(dir.path().join(path)).unwrap();
+        }
+        std::fs::write(dir.path().join(IGNORE_FILE), "fixtures/\n").unwrap();
+        std::fs::write(dir.path().join("a").join(IGNORE_FILE), "experiment\n").unwrap();
+
+        let discovery = Discovery {
+            no_ignore: true,
+            ..Default::default()
+        };
+        let mut dirs = walk(dir.path(), &discovery)
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_type().unwrap().is_dir())
+            .map(|entry| entry.path().strip_prefix(dir.path()).unwrap().to_owned())
+            .collect::<Vec<_>>();
+        dirs.sort();
+
+        assert_eq!(dirs, [Path::new(""), Path::new("a"), Path::new("a/kept")]);
+    }
+
+    #[test]
+    fn output_captures_stdout_and_stderr() {
+        let out = TaskContext::default()
+            .output(Command::new("sh").args(["-c", "echo out; echo err >&2"]))
+            .unwrap();
+
+        assert!(out.status.success());
+        assert_eq!(out.stdout, b"out\n");
+        assert_eq!(out.stderr, b"err\n");
+    }
+
+    #[test]
+    fn run_fails_with_stderr_and_stdout() {
+        let ctx = TaskContext::default();
+
+        ctx.run(&mut Command::new("true")).unwrap();
+        let error = ctx
+            .run(Command::new("sh").args(["-c", "echo out; echo err >&2; exit 1"]))
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "err\n\nout\n");
+    }
+
+    #[test]
+    fn output_sets_configured_env() {
+        let ctx = TaskContext {
+            env: [(String::from("GENTLE_TEST_VAR"), String::from("set"))].into(),
+            ..Default::default()
+        };
+
+        let out = ctx
+            .output(Command::new("sh").args(["-c", "echo $GENTLE_TEST_VAR"]))
+            .unwrap();
+
+        assert_eq!(out.stdout, b"set\n");
+    }
+
+    #[test]
+    fn captured_output_keeps_the_tail() {
+        let pipe = std::io::Cursor::new((0..100u8).collect::<Vec<_>>());
+
+        let captured = read_in_background(Some(pipe), Vec::new(), 10)
+            .join()
+            .unwrap();
+
+        assert_eq!(
+            captured,
+            [TRUNCATED, &(90..100u8).collect::<Vec<_>>()].concat()
+        );
+    }
+
+    #[test]
+    fn output_appends_to_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = TaskContext {
+            log: Some(dir.path().join("task.log")),
+            ..Default::default()
+        };
+
+        ctx.output(Command::new("echo").arg("one")).unwrap();
+        let out = ctx
+            .output(Command::new("sh").args(["-c", "echo two >&2"]))
+            .unwrap();
+
+        assert_eq!(out.stderr, b"two\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("task.log")).unwrap(),
+            "one\ntwo\n"
+        );
+    }
+
+    #[test]
+    fn output_appends_to_buffer() {
+        let ctx = TaskContext {
+            buffer: Some(Default::default()),
+            ..Default::default()
+        };
+
+        ctx.output(Command::new("echo").arg("one")).unwrap();
+        ctx.output(Command::new("sh").args(["-c", "echo two >&2"]))
+            .unwrap();
+
+        assert_eq!(*ctx.buffer.unwrap().lock().unwrap(), b"one\ntwo\n");
+    }
+
+    #[test]
+    fn output_kills_command_after_timeout() {
+        let ctx = TaskContext {
+            timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+
+        let started = Instant::now();
+        let result =
+            ctx.output(Command::new("sh").args(["-c", "echo out; echo err >&2; sleep 10"]));
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "killed after 200ms\nerr\n\nout\n"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn timeout_kills_grandchildren() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = TaskContext {
+            timeout: Some(Duration::from_millis(200)),
+            log: Some(dir.path().join("task.log")),
+            ..Default::default()
+        };
+
+        let result = ctx.output(Command::new("sh").args(["-c", "sleep 10 & echo $!; wait"]));
+        assert!(result.is_err());
+
+        let log = std::fs::read_to_string(dir.path().join("task.log")).unwrap();
+        let stat = format!("/proc/{}/stat", log.trim());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        // Until something reaps it, a killed process is a zombie.
+        while std::fs::read_to_string(&stat).is_ok_and(|s| !s.contains(") Z ")) {
+            assert!(Instant::now() < deadline, "grandchild still running");
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn fingerprint_changes_with_files_outside_excluded_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("target")).unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "one").unwrap();
+        let fingerprint = || {
+            Fingerprint::new()
+                .files(dir.path(), &["target"])
+                .unwrap()
+                .finish()
+        };
+
+        let before = fingerprint();
+        std::fs::write(dir.path().join("target/out"), "ignored").unwrap();
+        assert_eq!(fingerprint(), before);
+
+        std::fs::write(dir.path().join("lib.rs"), "two").unwrap();
+        assert_ne!(fingerprint(), before);
+    }
+}
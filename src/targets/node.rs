@@ -0,0 +1,156 @@
+use super::*;
+
+use serde::Deserialize;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "node",
+    discover,
+};
+
+/// Lockfiles by the package manager that writes them.
+const LOCKFILES: &[(&str, &str)] = &[
+    ("pnpm-lock.yaml", "pnpm"),
+    ("yarn.lock", "yarn"),
+    ("package-lock.json", "npm"),
+];
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    let manifest = path.join("package.json");
+    if !manifest.try_exists()? {
+        return Ok(Vec::new());
+    }
+    // Installed dependencies, which are only ignored by discovery when gitignored.
+    if path.components().any(|c| c.as_os_str() == "node_modules") {
+        return Ok(Vec::new());
+    }
+
+    let package: PackageJson = serde_json::from_slice(&std::fs::read(&manifest)?)
+        .map_err(|e| anyhow::anyhow!("parsing {}: {e}", manifest.display()))?;
+    if !package.scripts.contains_key("test") {
+        return Ok(Vec::new());
+    }
+
+    Ok(vec![Box::new(NodeTarget::new(path)?)])
+}
+
+#[derive(Deserialize)]
+struct PackageJson {
+    #[serde(default)]
+    scripts: HashMap<String, String>,
+}
+
+pub struct NodeTarget {
+    path: PathBuf,
+    package_manager: &'static str,
+}
+
+impl NodeTarget {
+    fn new(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            path: path.into(),
+            package_manager: package_manager(path)?,
+        })
+    }
+}
+
+/// The package manager of the nearest lockfile, which for a package of a pnpm or yarn workspace
+/// is at the workspace root. `npm` without one.
+fn package_manager(path: &Path) -> anyhow::Result<&'static str> {
+    for dir in path.ancestors() {
+        for (lockfile, package_manager) in LOCKFILES {
+            if dir.join(lockfile).try_exists()? {
+                return Ok(package_manager);
+            }
+        }
+    }
+    Ok("npm")
+}
+
+impl Display for NodeTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for NodeTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("node"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(
+            Command::new(self.package_manager)
+                .args(["test"])
+                .current_dir(&self.path),
+        )
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        [self.path.join("node_modules"), self.path.join(".cache")]
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TESTED: &str = r#"{"scripts": {"test": "jest"}}"#;
+
+    #[test]
+    fn package_without_a_test_script_is_skipped() {
+        let (_dir, targets) = discover_files(discover, &[("package.json", TESTED)]);
+        assert_eq!(targets.len(), 1);
+
+        let untested = r#"{"scripts": {"build": "tsc"}}"#;
+        assert!(discover_files(discover, &[("package.json", untested)])
+            .1
+            .is_empty());
+        assert!(discover_files(discover, &[("package.json", "{}")])
+            .1
+            .is_empty());
+    }
+
+    #[test]
+    fn lockfile_picks_the_package_manager() {
+        let manager = |files: &[(&str, &str)]| {
+            let (dir, _) = discover_files(discover, files);
+            package_manager(&dir.path().join("packages/a")).unwrap()
+        };
+
+        assert_eq!(manager(&[("packages/a/package.json", TESTED)]), "npm");
+        assert_eq!(
+            manager(&[
+                ("packages/a/yarn.lock", ""),
+                ("packages/a/package.json", TESTED)
+            ]),
+            "yarn"
+        );
+        // A workspace's lockfile is at its root.
+        assert_eq!(
+            manager(&[("pnpm-lock.yaml", ""), ("packages/a/package.json", TESTED)]),
+            "pnpm"
+        );
+    }
+
+    #[test]
+    fn installed_packages_are_skipped() {
+        let (dir, _) = discover_files(discover, &[("node_modules/left-pad/package.json", TESTED)]);
+
+        assert!(discover(
+            &dir.path().join("node_modules/left-pad"),
+            &Discovery::default()
+        )
+        .unwrap()
+        .is_empty());
+    }
+}
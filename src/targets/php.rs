@@ -0,0 +1,132 @@
+use super::*;
+
+use serde::Deserialize;
+
+const PHPUNIT_CONFIGS: &[&str] = &["phpunit.xml", "phpunit.xml.dist"];
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "php",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    let manifest = path.join("composer.json");
+    if !manifest.try_exists()? {
+        return Ok(Vec::new());
+    }
+
+    let composer: ComposerJson = serde_json::from_slice(&std::fs::read(&manifest)?)
+        .map_err(|e| anyhow::anyhow!("parsing {}: {e}", manifest.display()))?;
+    let runner = if composer.scripts.contains_key("test") {
+        TestRunner::Composer
+    } else if PHPUNIT_CONFIGS
+        .iter()
+        .any(|config| path.join(config).is_file())
+    {
+        TestRunner::Phpunit
+    } else {
+        return Ok(Vec::new());
+    };
+
+    Ok(vec![Box::new(PhpTarget::new(path, runner))])
+}
+
+#[derive(Deserialize)]
+struct ComposerJson {
+    /// A script is a command or a list of them.
+    #[serde(default)]
+    scripts: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum TestRunner {
+    /// `composer test`, the package's own `test` script.
+    Composer,
+    /// PHPUnit as installed into `vendor/` by Composer.
+    Phpunit,
+}
+
+pub struct PhpTarget {
+    path: PathBuf,
+    runner: TestRunner,
+}
+
+impl PhpTarget {
+    fn new(path: &Path, runner: TestRunner) -> Self {
+        Self {
+            path: path.into(),
+            runner,
+        }
+    }
+
+    fn command(&self) -> anyhow::Result<Command> {
+        let mut command = match self.runner {
+            TestRunner::Composer => {
+                let mut command = Command::new("composer");
+                command.arg("test");
+                command
+            }
+            // Like the Gradle wrapper, a relative program path isn't resolved against
+            // `current_dir` everywhere.
+            TestRunner::Phpunit => Command::new(
+                std::fs::canonicalize(self.path.join("vendor/bin/phpunit"))
+                    .map_err(|e| anyhow::anyhow!("finding phpunit, run `composer install`: {e}"))?,
+            ),
+        };
+        command.current_dir(&self.path);
+        Ok(command)
+    }
+}
+
+impl Display for PhpTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for PhpTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("php"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(&mut self.command()?)
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        [self.path.join("vendor")].into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discover_composer(composer: &str) -> Vec<Box<dyn Target>> {
+        discover_files(discover, &[("composer.json", composer)]).1
+    }
+
+    #[test]
+    fn test_script_or_phpunit_config_is_a_target() {
+        let targets = discover_composer(r#"{"scripts": {"test": ["phpunit"]}}"#);
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].address().identifier, "php");
+
+        let files = [("composer.json", "{}"), ("phpunit.xml.dist", "")];
+        assert_eq!(discover_files(discover, &files).1.len(), 1);
+    }
+
+    #[test]
+    fn library_without_tests_is_skipped() {
+        assert!(discover_composer(r#"{"scripts": {"lint": "phpcs"}}"#).is_empty());
+        assert!(discover_composer(r#"{"name": "acme/lib"}"#).is_empty());
+    }
+}
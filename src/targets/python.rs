@@ -0,0 +1,103 @@
+use super::*;
+
+const MANIFESTS: &[&str] = &["pyproject.toml", "setup.py", "setup.cfg"];
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "python",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    for manifest in MANIFESTS {
+        if path.join(manifest).try_exists()? {
+            return Ok(vec![Box::new(PythonTarget::new(path))]);
+        }
+    }
+
+    Ok(Vec::new())
+}
+
+pub struct PythonTarget {
+    path: PathBuf,
+}
+
+impl PythonTarget {
+    fn new(path: &Path) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn test_command(&self) -> Command {
+        test_command(on_path("pytest"))
+    }
+}
+
+/// Runs the tests with pytest if it's installed, since it also runs unittest's tests, and
+/// otherwise with unittest.
+fn test_command(pytest: bool) -> Command {
+    if pytest {
+        Command::new("pytest")
+    } else {
+        let mut command = Command::new("python");
+        command.args(["-m", "unittest"]);
+        command
+    }
+}
+
+impl Display for PythonTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for PythonTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("python"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        ctx.run(self.test_command().current_dir(&self.path))
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        [
+            self.path.join(".pytest_cache"),
+            self.path.join("__pycache__"),
+        ]
+        .into_iter()
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_manifest_makes_a_target() {
+        for manifest in MANIFESTS {
+            let (_dir, targets) = discover_files(discover, &[(manifest, "")]);
+
+            assert_eq!(targets.len(), 1, "{manifest}");
+            assert_eq!(targets[0].address().identifier, "python");
+        }
+        assert!(discover_files(discover, &[("main.py", "")]).1.is_empty());
+    }
+
+    #[test]
+    fn prefers_pytest_over_unittest() {
+        assert_eq!(test_command(true).get_program(), "pytest");
+        assert_eq!(test_command(true).get_args().count(), 0);
+
+        let unittest = test_command(false);
+        assert_eq!(unittest.get_program(), "python");
+        assert_eq!(unittest.get_args().collect::<Vec<_>>(), ["-m", "unittest"]);
+    }
+}
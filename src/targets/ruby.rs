@@ -0,0 +1,99 @@
+use super::*;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "ruby",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    if !path.join("Gemfile").try_exists()? {
+        return Ok(Vec::new());
+    }
+
+    let runner = if path.join("spec").is_dir() || path.join(".rspec").try_exists()? {
+        TestRunner::Rspec
+    } else if path.join("Rakefile").try_exists()? {
+        TestRunner::Rake
+    } else {
+        // A Gemfile alone is only dependencies, with nothing to test.
+        return Ok(Vec::new());
+    };
+
+    Ok(vec![Box::new(RubyTarget::new(path, runner))])
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum TestRunner {
+    Rspec,
+    Rake,
+}
+
+pub struct RubyTarget {
+    path: PathBuf,
+    runner: TestRunner,
+}
+
+impl RubyTarget {
+    fn new(path: &Path, runner: TestRunner) -> Self {
+        Self {
+            path: path.into(),
+            runner,
+        }
+    }
+}
+
+impl Display for RubyTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for RubyTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("ruby"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        let command: &[&str] = match self.runner {
+            TestRunner::Rspec => &["exec", "rspec"],
+            TestRunner::Rake => &["exec", "rake", "test"],
+        };
+        ctx.run(Command::new("bundle").args(command).current_dir(&self.path))
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        let bundle = self.path.join("vendor/bundle");
+        bundle.is_dir().then_some(bundle).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gemfile_with_tests_is_a_target() {
+        let (_dir, targets) = discover_files(discover, &[("Gemfile", ""), ("spec/a_spec.rb", "")]);
+        assert_eq!(targets[0].address().identifier, "ruby");
+        assert_eq!(
+            discover_files(discover, &[("Gemfile", ""), ("Rakefile", "")])
+                .1
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn bare_gemfile_is_skipped() {
+        assert!(discover_files(discover, &[("Gemfile", "")]).1.is_empty());
+        assert!(discover_files(discover, &[("Rakefile", "")]).1.is_empty());
+    }
+}
@@ -1,49 +1,683 @@
 use super::*;
 
+use serde::Deserialize;
+
 #[linkme::distributed_slice(TARGET_DISCOVERY)]
-fn discover(path: &Path) -> anyhow::Result<Vec<Box<dyn Target>>> {
-    if path.join("Cargo.toml").try_exists()? {
-        Ok(vec![(Box::new(RustCargoTarget::new(&path)))])
+static FACTORY: Factory = Factory {
+    name: "rust",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    let Some(manifest) = manifest(path)? else {
+        return Ok(Vec::new());
+    };
+
+    // Workspace members are tested by the workspace root's target.
+    if manifest.workspace.is_none() && in_workspace(path)? {
+        return Ok(Vec::new());
+    }
+
+    let workspace = manifest.workspace.is_some();
+    let config = package_config::<PackageConfig>(path)?.rust;
+    let crates = if config.features.is_empty() {
+        vec![RustCargoTarget::new(path, workspace, None, &config)]
+    } else {
+        config
+            .features
+            .clone()
+            .into_iter()
+            .map(|feature_set| RustCargoTarget::new(path, workspace, Some(feature_set), &config))
+            .collect()
+    };
+
+    let mut targets = Vec::new();
+    for target in crates {
+        let doctests = config.separate_doctests.then(|| target.doctests());
+        targets.push(Box::new(target) as Box<dyn Target>);
+        if let Some(doctests) = doctests {
+            targets.push(Box::new(doctests));
+        }
+    }
+    Ok(targets)
+}
+
+#[derive(Deserialize, Default)]
+struct PackageConfig {
+    #[serde(default)]
+    rust: RustConfig,
+}
+
+/// The `[rust]` section of a crate's `gentle.toml`.
+#[derive(Deserialize, Default)]
+struct RustConfig {
+    /// Cargo flags by name, each run as its own target, e.g.
+    /// `all-features = ["--all-features"]` becomes `//foo:rust_crate[all-features]`.
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+    /// Cargo's `--jobs`, instead of this crate's share of the cores.
+    jobs: Option<usize>,
+    /// Test with `cargo nextest`, and doctests with `cargo test --doc`, when it's installed.
+    #[serde(default)]
+    nextest: bool,
+    /// Run doctests as their own `rust_doctest` target, in parallel with the crate's other tests.
+    #[serde(default)]
+    separate_doctests: bool,
+}
+
+fn nextest_installed() -> bool {
+    static INSTALLED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *INSTALLED.get_or_init(|| {
+        Command::new("cargo")
+            .args(["nextest", "--version"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CoverageTool {
+    LlvmCov,
+    Tarpaulin,
+}
+
+/// The installed cargo coverage subcommand, preferring `cargo llvm-cov`.
+fn coverage_tool() -> Option<CoverageTool> {
+    static TOOL: std::sync::OnceLock<Option<CoverageTool>> = std::sync::OnceLock::new();
+    *TOOL.get_or_init(|| {
+        [
+            (CoverageTool::LlvmCov, "llvm-cov"),
+            (CoverageTool::Tarpaulin, "tarpaulin"),
+        ]
+        .into_iter()
+        .find(|(_, subcommand)| {
+            Command::new("cargo")
+                .args([subcommand, "--version"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success())
+        })
+        .map(|(tool, _)| tool)
+    })
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    workspace: Option<Workspace>,
+}
+
+#[derive(Deserialize)]
+struct Workspace {
+    #[serde(default)]
+    exclude: Vec<PathBuf>,
+}
+
+fn manifest(dir: &Path) -> anyhow::Result<Option<Manifest>> {
+    let path = dir.join("Cargo.toml");
+    if !path.try_exists()? {
+        return Ok(None);
+    }
+
+    let manifest = toml::from_slice(&std::fs::read(&path)?)
+        .map_err(|e| anyhow::anyhow!("parsing {}: {e}", path.display()))?;
+    Ok(Some(manifest))
+}
+
+/// Whether an ancestor's workspace contains the crate at `path`. Cargo requires every crate under
+/// a workspace root to be a member unless it's excluded, so the `members` globs needn't be checked.
+fn in_workspace(path: &Path) -> anyhow::Result<bool> {
+    for ancestor in path.ancestors().skip(1) {
+        let Some(Manifest {
+            workspace: Some(workspace),
+        }) = manifest(ancestor)?
+        else {
+            continue;
+        };
+
+        let relative = path.strip_prefix(ancestor)?;
+        return Ok(!workspace
+            .exclude
+            .iter()
+            .any(|excluded| relative.starts_with(excluded)));
+    }
+
+    Ok(false)
+}
+
+/// The parts of `cargo metadata` that affect a crate's fingerprint.
+#[derive(Deserialize)]
+struct Metadata {
+    /// Every package the crate or workspace depends on, and its own.
+    packages: Vec<MetadataPackage>,
+    workspace_root: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct MetadataPackage {
+    manifest_path: PathBuf,
+    /// Where the package comes from, like a registry, or `None` for a path dependency.
+    source: Option<String>,
+}
+
+fn metadata(path: &Path) -> anyhow::Result<Metadata> {
+    let out = Command::new("cargo")
+        .args(["metadata", "--format-version", "1"])
+        .current_dir(path)
+        .stdin(Stdio::null())
+        .output()?;
+    anyhow::ensure!(
+        out.status.success(),
+        "cargo metadata failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    Ok(serde_json::from_slice(&out.stdout)?)
+}
+
+/// Cargo's config files that apply in `path`, whether or not they exist: those in `.cargo` in
+/// it and its ancestors, then in `$CARGO_HOME`.
+fn cargo_configs(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")));
+    let dirs = std::fs::canonicalize(path)?
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home)
+        .collect::<Vec<_>>();
+    Ok(dirs
+        .iter()
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config")])
+        .collect())
+}
+
+fn color_flag(ctx: &TaskContext) -> &'static str {
+    if ctx.color {
+        "--color=always"
     } else {
-        Ok(Vec::new())
+        "--color=never"
     }
 }
 
+/// Bytes of source a crate has per unit of weight.
+const WEIGHT_UNIT: u64 = 256 * 1024;
+
+/// Which of a crate's tests a target runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tests {
+    All,
+    /// Everything but doctests, which another target runs.
+    AllTargets,
+    Doc,
+}
+
+#[derive(Clone)]
 pub struct RustCargoTarget {
     path: PathBuf,
+    workspace: bool,
+    tests: Tests,
+    /// Name and cargo flags of the feature set to build with, if not the default.
+    feature_set: Option<(String, Vec<String>)>,
+    jobs: Option<usize>,
+    nextest: bool,
 }
 
 impl RustCargoTarget {
-    fn new(path: &Path) -> Self {
-        Self { path: path.into() }
+    fn new(
+        path: &Path,
+        workspace: bool,
+        feature_set: Option<(String, Vec<String>)>,
+        config: &RustConfig,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            workspace,
+            tests: if config.separate_doctests {
+                Tests::AllTargets
+            } else {
+                Tests::All
+            },
+            feature_set,
+            jobs: config.jobs,
+            nextest: config.nextest,
+        }
+    }
+
+    /// The target that runs this crate's doctests.
+    fn doctests(&self) -> Self {
+        Self {
+            tests: Tests::Doc,
+            ..self.clone()
+        }
+    }
+
+    /// `cargo test --doc` fails without a library.
+    fn has_doctests(&self) -> anyhow::Result<bool> {
+        Ok(self.workspace || self.path.join("src/lib.rs").try_exists()?)
+    }
+
+    /// Jobs for cargo, if either this crate or the task context limits them.
+    fn jobs(&self, ctx: &TaskContext) -> Option<usize> {
+        self.jobs.or(ctx.jobs)
+    }
+
+    fn feature_flags(&self) -> &[String] {
+        self.feature_set.as_ref().map_or(&[], |(_, flags)| flags)
+    }
+
+    /// `cargo <args>` on this crate with its features, `jobs_flag` set to its jobs.
+    fn cargo(&self, ctx: &TaskContext, args: &[&str], jobs_flag: &str) -> Command {
+        let mut command = Command::new("cargo");
+        command
+            .args(args)
+            .args([
+                "--manifest-path",
+                &self.path.join("Cargo.toml").to_string_lossy(),
+                color_flag(ctx),
+            ])
+            .args(self.jobs(ctx).map(|jobs| format!("{jobs_flag}={jobs}")))
+            .args(self.all_members("--workspace"))
+            .args(self.feature_flags());
+        command
+    }
+
+    /// `cargo doc` on this crate, failing on warnings.
+    fn doc_command(&self, ctx: &TaskContext) -> Command {
+        let mut command = self.cargo(ctx, &["doc", "--no-deps"], "--jobs");
+        let flags = std::env::var("RUSTDOCFLAGS").unwrap_or_default();
+        command.env("RUSTDOCFLAGS", format!("{flags} -Dwarnings").trim_start());
+        command
+    }
+
+    /// `cargo llvm-cov` or `cargo tarpaulin` on this crate, writing lcov into `out_dir`.
+    fn coverage_command(&self, ctx: &TaskContext, tool: CoverageTool, out_dir: &Path) -> Command {
+        let out_dir = out_dir.to_string_lossy();
+        match tool {
+            CoverageTool::LlvmCov => self.cargo(
+                ctx,
+                &[
+                    "llvm-cov",
+                    "--lcov",
+                    "--output-path",
+                    &format!("{out_dir}/lcov.info"),
+                ],
+                "--jobs",
+            ),
+            CoverageTool::Tarpaulin => self.cargo(
+                ctx,
+                &["tarpaulin", "--out", "Lcov", "--output-dir", &out_dir],
+                "--jobs",
+            ),
+        }
+    }
+
+    /// `cargo run` on this crate, passing `args` to the program.
+    fn run_command(&self, args: &[String]) -> Command {
+        let mut command = Command::new("cargo");
+        command
+            .args([
+                "run",
+                "--manifest-path",
+                &self.path.join("Cargo.toml").to_string_lossy(),
+            ])
+            .args(self.feature_flags())
+            .arg("--")
+            .args(args);
+        command
+    }
+
+    /// `flag` if this is a workspace root, to have cargo act on every member.
+    fn all_members(&self, flag: &'static str) -> Option<&'static str> {
+        self.workspace.then_some(flag)
     }
 }
 
 impl Display for RustCargoTarget {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // TODO(shelbyd): De-duplicate formatting of target addresses.
-        let package = self.path.display().to_string().replacen("./", "", 1);
-        write!(f, "//{package}:rust_crate")
+        write!(f, "{}", self.address())
     }
 }
 
 impl Target for RustCargoTarget {
-    fn perform_test(&self) -> anyhow::Result<()> {
-        Command::new("cargo")
-            .args(&[
-                "test",
-                "--manifest-path",
-                &self.path.join("Cargo.toml").to_string_lossy(),
-                "--jobs=1",
-                "--color=always",
-            ])
-            .output()?
-            .success_ok()
-            .map(|_| ())
-            .map_err(|out| anyhow::anyhow!(format!("{}\n{}", out.stderr, out.stdout)))
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: {
+                let kind = match self.tests {
+                    Tests::Doc => "rust_doctest",
+                    Tests::All | Tests::AllTargets => "rust_crate",
+                };
+                match &self.feature_set {
+                    Some((name, _)) => format!("{kind}[{name}]"),
+                    None => String::from(kind),
+                }
+            },
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        if self.tests == Tests::Doc {
+            if !self.has_doctests()? {
+                return Ok(());
+            }
+            return ctx.run(&mut self.cargo(ctx, &["test", "--doc"], "--jobs"));
+        }
+
+        if !(self.nextest && nextest_installed()) {
+            let args: &[&str] = match self.tests {
+                Tests::AllTargets => &["test", "--all-targets"],
+                _ => &["test"],
+            };
+            return ctx.run(&mut self.cargo(ctx, args, "--jobs"));
+        }
+
+        ctx.run(&mut self.cargo(ctx, &["nextest", "run"], "--build-jobs"))?;
+        // nextest doesn't run doctests.
+        if self.tests == Tests::All && self.has_doctests()? {
+            ctx.run(&mut self.cargo(ctx, &["test", "--doc"], "--jobs"))?;
+        }
+        Ok(())
+    }
+
+    fn perform_build(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        // The crate's own target builds it.
+        if self.tests == Tests::Doc {
+            return Ok(());
+        }
+        ctx.run(&mut self.cargo(ctx, &["build"], "--jobs"))
+    }
+
+    fn perform_check(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        if self.tests == Tests::Doc {
+            return Ok(());
+        }
+        ctx.run(&mut self.cargo(ctx, &["check", "--all-targets"], "--jobs"))
+    }
+
+    fn perform_doc(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        if self.tests == Tests::Doc {
+            return Ok(());
+        }
+        ctx.run(&mut self.doc_command(ctx))
+    }
+
+    fn perform_fmt_check(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        if self.tests == Tests::Doc {
+            return Ok(());
+        }
+        ctx.run(
+            Command::new("cargo")
+                .args([
+                    "fmt",
+                    "--manifest-path",
+                    &self.path.join("Cargo.toml").to_string_lossy(),
+                ])
+                .args(self.all_members("--all"))
+                .args(["--", "--check", color_flag(ctx)]),
+        )
+    }
+
+    fn perform_coverage(&self, ctx: &TaskContext, out: &Path) -> anyhow::Result<()> {
+        // The crate's own target measures its doctests too.
+        if self.tests == Tests::Doc {
+            return Ok(());
+        }
+        let Some(tool) = coverage_tool() else {
+            return Ok(());
+        };
+
+        // Tarpaulin only lets us choose the directory its `lcov.info` goes in.
+        let out_dir = out.with_extension("d");
+        std::fs::create_dir_all(&out_dir)?;
+        let result = ctx
+            .run(&mut self.coverage_command(ctx, tool, &out_dir))
+            .and_then(|()| Ok(std::fs::rename(out_dir.join("lcov.info"), out)?));
+        let _ = std::fs::remove_dir_all(&out_dir);
+        result
+    }
+
+    fn perform_run(&self, args: &[String]) -> anyhow::Result<()> {
+        run_attached(&mut self.run_command(args))
     }
 
     fn cache_paths(&self) -> HashSet<PathBuf> {
         [self.path.join("target")].into_iter().collect()
     }
+
+    fn cache_excludes(&self) -> Vec<String> {
+        // Incremental compilation state is large and rarely reusable on another machine.
+        vec![String::from("**/incremental/**")]
+    }
+
+    fn input_fingerprint(&self) -> anyhow::Result<Option<String>> {
+        let metadata = metadata(&self.path)?;
+        let mut fingerprint = Fingerprint::new().files(&self.path, &["target"])?;
+        // Path dependencies, including those outside this crate or workspace.
+        for package in &metadata.packages {
+            if package.source.is_none() {
+                if let Some(dir) = package.manifest_path.parent() {
+                    fingerprint = fingerprint.files(dir, &["target"])?;
+                }
+            }
+        }
+        fingerprint = fingerprint.file(&metadata.workspace_root.join("Cargo.lock"))?;
+        for config in cargo_configs(&self.path)? {
+            fingerprint = fingerprint.file(&config)?;
+        }
+        Ok(Some(fingerprint.tool("rustc", &["-vV"])?.finish()))
+    }
+
+    fn weight(&self) -> u32 {
+        // Compiling is what's expensive, and it grows with the amount of source.
+        source_weight(&self.path, &["target"], WEIGHT_UNIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_covers_path_dependencies_and_the_lockfile() {
+        let (dir, _) = discover_files(
+            discover,
+            &[
+                (
+                    "a/Cargo.toml",
+                    "[package]\nname = \"a\"\nversion = \"0.1.0\"\n\n\
+                     [dependencies]\nb = { path = \"../b\" }\n",
+                ),
+                ("a/src/lib.rs", ""),
+                (
+                    "b/Cargo.toml",
+                    "[package]\nname = \"b\"\nversion = \"0.1.0\"\n",
+                ),
+                ("b/src/lib.rs", ""),
+            ],
+        );
+        let targets = discover(&dir.path().join("a"), &Discovery::default()).unwrap();
+        let fingerprint = || targets[0].input_fingerprint().unwrap().unwrap();
+
+        let before = fingerprint();
+        assert_eq!(fingerprint(), before);
+
+        std::fs::write(dir.path().join("b/src/lib.rs"), "pub fn b() {}").unwrap();
+        let changed = fingerprint();
+        assert_ne!(changed, before);
+
+        std::fs::write(dir.path().join("a/Cargo.lock"), "# edited\n").unwrap();
+        assert_ne!(fingerprint(), changed);
+    }
+
+    #[test]
+    fn feature_sets_become_separate_targets() {
+        let (_dir, targets) = discover_files(
+            discover,
+            &[
+                ("Cargo.toml", "[package]\nname = \"a\"\n"),
+                (
+                    "gentle.toml",
+                    "[rust.features]\nall-features = [\"--all-features\"]\nbare = [\"--no-default-features\"]\n",
+                ),
+            ],
+        );
+
+        let identifiers = targets
+            .iter()
+            .map(|t| t.address().identifier)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            identifiers,
+            ["rust_crate[all-features]", "rust_crate[bare]"]
+        );
+    }
+
+    #[test]
+    fn separate_doctests_get_their_own_target() {
+        let (_dir, targets) = discover_files(
+            discover,
+            &[
+                ("Cargo.toml", "[package]\nname = \"a\"\n"),
+                ("gentle.toml", "[rust]\nseparate_doctests = true\n"),
+            ],
+        );
+
+        let identifiers = targets
+            .iter()
+            .map(|t| t.address().identifier)
+            .collect::<Vec<_>>();
+
+        assert_eq!(identifiers, ["rust_crate", "rust_doctest"]);
+    }
+
+    #[test]
+    fn crate_jobs_override_the_share_of_cores() {
+        let ctx = TaskContext {
+            jobs: Some(4),
+            ..Default::default()
+        };
+
+        let config = RustConfig::default();
+        let target = RustCargoTarget::new(Path::new("a"), false, None, &config);
+        assert_eq!(target.jobs(&ctx), Some(4));
+
+        let config = RustConfig {
+            jobs: Some(1),
+            ..Default::default()
+        };
+        let target = RustCargoTarget::new(Path::new("a"), false, None, &config);
+        assert_eq!(target.jobs(&ctx), Some(1));
+    }
+
+    #[test]
+    fn cargo_command_has_crate_flags() {
+        let ctx = TaskContext {
+            jobs: Some(2),
+            ..Default::default()
+        };
+        let target = RustCargoTarget::new(
+            Path::new("a"),
+            true,
+            Some((String::from("all"), vec![String::from("--all-features")])),
+            &RustConfig::default(),
+        );
+
+        let command = target.cargo(&ctx, &["nextest", "run"], "--build-jobs");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "nextest",
+                "run",
+                "--manifest-path",
+                "a/Cargo.toml",
+                "--color=never",
+                "--build-jobs=2",
+                "--workspace",
+                "--all-features",
+            ]
+        );
+    }
+
+    #[test]
+    fn run_passes_args_to_the_program() {
+        let target = RustCargoTarget::new(
+            Path::new("a"),
+            false,
+            Some((String::from("all"), vec![String::from("--all-features")])),
+            &RustConfig::default(),
+        );
+
+        let command = target.run_command(&[String::from("--flag")]);
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "run",
+                "--manifest-path",
+                "a/Cargo.toml",
+                "--all-features",
+                "--",
+                "--flag"
+            ]
+        );
+    }
+
+    #[test]
+    fn doc_fails_on_warnings() {
+        let target = RustCargoTarget::new(Path::new("a"), false, None, &RustConfig::default());
+
+        let command = target.doc_command(&TaskContext::default());
+        assert_eq!(
+            command.get_args().take(2).collect::<Vec<_>>(),
+            ["doc", "--no-deps"]
+        );
+        let flags = command
+            .get_envs()
+            .find(|(key, _)| *key == "RUSTDOCFLAGS")
+            .and_then(|(_, value)| value)
+            .unwrap();
+        assert!(flags.to_string_lossy().ends_with("-Dwarnings"));
+    }
+
+    #[test]
+    fn workspace_members_are_tested_by_the_root() {
+        let (dir, targets) = discover_files(
+            discover,
+            &[
+                (
+                    "Cargo.toml",
+                    "[workspace]\nmembers = [\"a\", \"b\"]\nexclude = [\"c\"]\n",
+                ),
+                ("a/Cargo.toml", "[package]\nname = \"a\"\n"),
+                ("b/Cargo.toml", "[package]\nname = \"b\"\n"),
+                ("c/Cargo.toml", "[package]\nname = \"c\"\n"),
+            ],
+        );
+        let root = dir.path();
+
+        assert_eq!(targets.len(), 1);
+        assert_eq!(
+            targets[0].cache_paths(),
+            [root.join("target")].into_iter().collect()
+        );
+
+        assert!(discover(&root.join("a"), &Discovery::default())
+            .unwrap()
+            .is_empty());
+        assert!(discover(&root.join("b"), &Discovery::default())
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            discover(&root.join("c"), &Discovery::default())
+                .unwrap()
+                .len(),
+            1
+        );
+    }
 }
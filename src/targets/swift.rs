@@ -0,0 +1,89 @@
+use super::*;
+
+#[linkme::distributed_slice(TARGET_DISCOVERY)]
+static FACTORY: Factory = Factory {
+    name: "swift",
+    discover,
+};
+
+fn discover(path: &Path, _: &Discovery) -> anyhow::Result<Vec<Box<dyn Target>>> {
+    if !path.join("Package.swift").try_exists()? {
+        return Ok(Vec::new());
+    }
+    // `swift test` fails when the package has no tests, as libraries often don't.
+    if !path.join("Tests").is_dir() {
+        return Ok(Vec::new());
+    }
+
+    Ok(vec![Box::new(SwiftTarget::new(path))])
+}
+
+pub struct SwiftTarget {
+    path: PathBuf,
+}
+
+impl SwiftTarget {
+    fn new(path: &Path) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn swift(&self, ctx: &TaskContext, subcommand: &str) -> anyhow::Result<()> {
+        let mut command = Command::new("swift");
+        command.arg(subcommand).current_dir(&self.path);
+        if let Some(jobs) = ctx.jobs {
+            command.arg(format!("--jobs={jobs}"));
+        }
+
+        ctx.run(&mut command)
+    }
+}
+
+impl Display for SwiftTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+impl Target for SwiftTarget {
+    fn address(&self) -> TargetAddress {
+        TargetAddress {
+            package: package(&self.path),
+            identifier: String::from("swift"),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn perform_test(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        self.swift(ctx, "test")
+    }
+
+    fn perform_build(&self, ctx: &TaskContext) -> anyhow::Result<()> {
+        self.swift(ctx, "build")
+    }
+
+    fn cache_paths(&self) -> HashSet<PathBuf> {
+        [self.path.join(".build")].into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_with_tests_is_a_target() {
+        assert!(discover_files(discover, &[("Package.swift", "")])
+            .1
+            .is_empty());
+
+        let (_dir, targets) = discover_files(
+            discover,
+            &[("Package.swift", ""), ("Tests/AppTests/AppTests.swift", "")],
+        );
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].address().identifier, "swift");
+    }
+}
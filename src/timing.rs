@@ -0,0 +1,88 @@
+use serde::Serialize;
+use std::path::Path;
+
+use gentle::multi_runner::{RunSummary, TaskError};
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct Entry<'s> {
+    action: &'s str,
+    address: &'s str,
+    duration_ms: u128,
+    outcome: &'static str,
+}
+
+pub fn write_report<E>(path: &Path, summary: &RunSummary<E>) -> anyhow::Result<()> {
+    std::fs::write(path, serde_json::to_vec_pretty(&entries(summary))?)?;
+    Ok(())
+}
+
+fn entries<E>(summary: &RunSummary<E>) -> Vec<Entry<'_>> {
+    summary
+        .results
+        .iter()
+        .map(|result| Entry {
+            action: &result.action,
+            address: &result.address,
+            duration_ms: result.duration.as_millis(),
+            outcome: match &result.result {
+                Ok(()) => "passed",
+                Err(TaskError::Failed(_)) => "failed",
+                Err(TaskError::Panicked(_)) => "panicked",
+                Err(TaskError::TimedOut(_)) => "timed_out",
+                Err(TaskError::DependencyFailed(_)) => "dependency_failed",
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gentle::multi_runner::TaskResult;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn one_entry_per_task() {
+        let summary = RunSummary::<()> {
+            results: vec![
+                TaskResult {
+                    name: String::from("test //a:rust_crate"),
+                    action: String::from("test"),
+                    address: String::from("//a:rust_crate"),
+                    started: Instant::now(),
+                    duration: Duration::from_millis(1500),
+                    slot: None,
+                    result: Ok(()),
+                },
+                TaskResult {
+                    name: String::from("build //b:go_mod"),
+                    action: String::from("build"),
+                    address: String::from("//b:go_mod"),
+                    started: Instant::now(),
+                    duration: Duration::from_millis(20),
+                    slot: None,
+                    result: Err(TaskError::TimedOut(Duration::from_millis(20))),
+                },
+            ],
+        };
+
+        assert_eq!(
+            entries(&summary),
+            vec![
+                Entry {
+                    action: "test",
+                    address: "//a:rust_crate",
+                    duration_ms: 1500,
+                    outcome: "passed",
+                },
+                Entry {
+                    action: "build",
+                    address: "//b:go_mod",
+                    duration_ms: 20,
+                    outcome: "timed_out",
+                },
+            ]
+        );
+    }
+}
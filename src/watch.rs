@@ -0,0 +1,131 @@
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+    sync::{mpsc::channel, Arc},
+    time::Duration,
+};
+
+use crate::state::STATE_FILE;
+use gentle::targets::Target;
+
+/// How long the tree must be quiet before re-running, so saving many files runs once.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Calls `on_change` with the targets whose directories changed, forever. Changes to `outputs`,
+/// the files and directories runs write reports and logs to, are ignored.
+pub fn watch(
+    targets: &[Arc<dyn Target>],
+    outputs: &[PathBuf],
+    mut on_change: impl FnMut(&[Arc<dyn Target>]),
+) -> anyhow::Result<()> {
+    let root = std::env::current_dir()?;
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+
+    let ignored = ignored(&root, targets, outputs);
+
+    eprintln!("Watching for changes");
+    loop {
+        let mut changed = HashSet::new();
+        let mut next = receiver.recv()?;
+        loop {
+            let event = next?;
+            if !matches!(event.kind, EventKind::Access(_)) {
+                changed.extend(
+                    event
+                        .paths
+                        .iter()
+                        .map(|path| relative(&root, path))
+                        .filter(|path| !ignored.iter().any(|i| path.starts_with(i))),
+                );
+            }
+
+            match receiver.recv_timeout(DEBOUNCE) {
+                Ok(event) => next = event,
+                Err(_) => break,
+            }
+        }
+
+        let affected = targets
+            .iter()
+            .filter(|t| affects(&t.address().package, &changed))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !affected.is_empty() {
+            on_change(&affected);
+            eprintln!("Watching for changes");
+        }
+    }
+}
+
+/// Paths, relative to `root`, that runs write to, which would otherwise re-run them forever.
+fn ignored(root: &Path, targets: &[Arc<dyn Target>], outputs: &[PathBuf]) -> Vec<PathBuf> {
+    targets
+        .iter()
+        .flat_map(|t| t.cache_paths())
+        .chain(outputs.iter().cloned())
+        .map(|path| relative(root, &path))
+        .chain([PathBuf::from(STATE_FILE), PathBuf::from(".git")])
+        .collect()
+}
+
+/// `path` relative to `root`, without any `./`.
+fn relative(root: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+/// Whether any of the `changed` paths, relative to the project root, is in `package`.
+pub fn affects(package: &str, changed: &HashSet<PathBuf>) -> bool {
+    changed.iter().any(|path| path.starts_with(package))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn change_affects_packages_containing_it() {
+        let changed = [PathBuf::from("backend/api/src/lib.rs")].into();
+
+        assert!(affects("backend/api", &changed));
+        assert!(affects("", &changed));
+        assert!(!affects("backend/web", &changed));
+        assert!(!affects("backend/ap", &changed));
+    }
+
+    #[test]
+    fn relative_strips_root_and_current_dir() {
+        let root = Path::new("/work/project");
+
+        assert_eq!(
+            relative(root, Path::new("/work/project/r/target")),
+            Path::new("r/target")
+        );
+        assert_eq!(
+            relative(root, Path::new("./r/target")),
+            Path::new("r/target")
+        );
+    }
+
+    #[test]
+    fn outputs_are_ignored() {
+        let root = Path::new("/work/project");
+        let outputs = [
+            PathBuf::from("./reports/junit.xml"),
+            PathBuf::from("/work/project/logs"),
+            PathBuf::from("/tmp/profile.json"),
+        ];
+
+        let ignored = ignored(root, &[], &outputs);
+
+        for path in ["reports/junit.xml", "logs", "/tmp/profile.json", STATE_FILE] {
+            assert!(ignored.contains(&PathBuf::from(path)), "{path} not ignored");
+        }
+    }
+}